@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use na::{Matrix4, Point3, Vector2, Vector3, Vector4};
+
+use crate::rendering::Projection;
+use crate::transform::Transform;
+use crate::util::Rfc;
+
+/// Region of the canvas, in pixels, that a camera draws to.
+/// The origin is the top left corner of the canvas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width / self.height
+    }
+
+    /// The x, y, width and height to pass to `gl.viewport`, which measures
+    /// y up from the bottom of a canvas `canvas_height` pixels tall.
+    pub fn gl_rect(&self, canvas_height: f32) -> (i32, i32, i32, i32) {
+        (
+            self.x as i32,
+            (canvas_height - self.y - self.height) as i32,
+            self.width as i32,
+            self.height as i32,
+        )
+    }
+}
+
+/// A camera looking down its local -Z axis, with local +Y as up.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    projection: Projection,
+    tf: Rfc<Transform>,
+    viewport: Viewport,
+}
+
+impl Camera {
+    pub fn new(projection: Projection, viewport: Viewport) -> Self {
+        Self {
+            projection,
+            tf: Rc::new(RefCell::new(Transform::identity())),
+            viewport,
+        }
+    }
+
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) -> &mut Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn get_viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    pub fn set_viewport(&mut self, viewport: Viewport) -> &mut Self {
+        self.viewport = viewport;
+        self
+    }
+
+    /// Return the transform placing this camera in the world.
+    pub fn get_tf_rc(&self) -> Rfc<Transform> {
+        self.tf.clone()
+    }
+
+    /// Move the camera to `eye` and rotate it to face `target`.
+    pub fn look_at(&mut self, eye: Vector3<f32>, target: Vector3<f32>, up: Vector3<f32>) {
+        self.tf.borrow_mut().set_position(eye).look_at(target, up);
+    }
+
+    /// Sit at the origin looking down world +Z, with +Y pointing down the
+    /// screen. With an orthographic projection this keeps the mapping from
+    /// before cameras existed: x right and y down from the centre of the
+    /// view, with depth increasing along z.
+    pub fn look_down_z(&mut self) {
+        self.look_at(Vector3::zeros(), Vector3::z(), -Vector3::y());
+    }
+
+    pub fn view_mat(&self) -> Matrix4<f32> {
+        self.tf
+            .borrow()
+            .world_mat()
+            .try_inverse()
+            .expect("Camera transform must be invertible")
+    }
+
+    pub fn projection_mat(&self) -> Matrix4<f32> {
+        self.projection.to_mat4()
+    }
+
+    /// Return the matrix taking world space to clip space.
+    pub fn view_projection_mat(&self) -> Matrix4<f32> {
+        self.projection_mat() * self.view_mat()
+    }
+
+    /// Convert a world position to viewport pixel coordinates.
+    /// Returns `None` for points behind the camera.
+    pub fn world_to_screen(&self, world_pos: Vector3<f32>) -> Option<Vector2<f32>> {
+        let clip = self.view_projection_mat() * world_pos.push(1.);
+        if clip[3] <= 0. {
+            return None;
+        }
+        let ndc = clip.xyz() / clip[3];
+        Some(Vector2::new(
+            self.viewport.x + (ndc[0] + 1.) * 0.5 * self.viewport.width,
+            self.viewport.y + (1. - ndc[1]) * 0.5 * self.viewport.height,
+        ))
+    }
+
+    /// Convert viewport pixel coordinates to a world position.
+    ///
+    /// # Arguments
+    ///
+    /// * `screen_pos` - Pixel coordinates, with the origin at the top left.
+    /// * `ndc_depth` - Depth in normalised device coordinates, from -1 (near) to 1 (far).
+    pub fn screen_to_world(
+        &self,
+        screen_pos: Vector2<f32>,
+        ndc_depth: f32,
+    ) -> Option<Vector3<f32>> {
+        let inv = self.view_projection_mat().try_inverse()?;
+        let ndc = Vector4::new(
+            (screen_pos[0] - self.viewport.x) / self.viewport.width * 2. - 1.,
+            1. - (screen_pos[1] - self.viewport.y) / self.viewport.height * 2.,
+            ndc_depth,
+            1.,
+        );
+        let world = inv * ndc;
+        if world[3] == 0. {
+            return None;
+        }
+        Some(world.xyz() / world[3])
+    }
+
+    /// Return the ray passing through the given pixel, as an origin on the
+    /// near plane and a normalised direction.
+    pub fn screen_to_ray(&self, screen_pos: Vector2<f32>) -> Option<(Point3<f32>, Vector3<f32>)> {
+        let near = self.screen_to_world(screen_pos, -1.)?;
+        let far = self.screen_to_world(screen_pos, 1.)?;
+        Some((Point3::from(near), (far - near).normalize()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maths_utils::Angle;
+
+    fn perspective_camera() -> Camera {
+        let mut camera = Camera::new(
+            Projection::Perspective {
+                fov_y: Angle::from_deg(60.),
+                aspect: 2.,
+                near: 0.1,
+                far: 100.,
+            },
+            Viewport::new(0., 0., 200., 100.),
+        );
+        camera.look_at(
+            Vector3::new(0., 0., 10.),
+            Vector3::zeros(),
+            Vector3::new(0., 1., 0.),
+        );
+        camera
+    }
+
+    #[test]
+    fn gl_viewport_counts_up_from_the_bottom() {
+        let full = Viewport::new(0., 0., 300., 200.);
+        assert_eq!(full.gl_rect(200.), (0, 0, 300, 200));
+        // The top right quarter of the canvas.
+        let corner = Viewport::new(150., 0., 150., 100.);
+        assert_eq!(corner.gl_rect(200.), (150, 100, 150, 100));
+        let bottom = Viewport::new(0., 150., 300., 50.);
+        assert_eq!(bottom.gl_rect(200.), (0, 0, 300, 50));
+    }
+
+    #[test]
+    fn orthographic_keeps_old_mapping() {
+        let (width, height, depth) = (30., 20., 400.);
+        let mut camera = Camera::new(
+            Projection::Orthographic {
+                view_width: width,
+                view_height: height,
+                clip_depth: depth,
+            },
+            Viewport::new(0., 0., 300., 200.),
+        );
+        camera.look_down_z();
+        // The old orthographic renderer's projection, and its camera shift.
+        let old_projection = Matrix4::from_columns(&[
+            Vector4::new(2. / width, 0., 0., 0.),
+            Vector4::new(0., -2. / height, 0., 0.),
+            Vector4::new(0., 0., 2. / depth, 0.),
+            Vector4::new(-1., 1., 0., 1.),
+        ]);
+        let old_mat =
+            old_projection * Matrix4::new_translation(&Vector3::new(width * 0.5, height * 0.5, 0.));
+        let diff = camera.view_projection_mat() - old_mat;
+        assert!(diff.abs().max() < 1e-6, "{}", diff);
+    }
+
+    #[test]
+    fn target_projects_to_viewport_centre() {
+        let camera = perspective_camera();
+        let screen = camera.world_to_screen(Vector3::zeros()).unwrap();
+        assert!((screen - Vector2::new(100., 50.)).norm() < 0.001);
+        assert!(camera.world_to_screen(Vector3::new(0., 0., 20.)).is_none());
+    }
+
+    #[test]
+    fn screen_to_world_round_trip() {
+        let camera = perspective_camera();
+        let world = Vector3::new(1.5, -2., 3.);
+        let screen = camera.world_to_screen(world).unwrap();
+        let (origin, dir) = camera.screen_to_ray(screen).unwrap();
+        let to_world = world - origin.coords;
+        assert!(to_world.normalize().dot(&dir) > 0.9999);
+    }
+}
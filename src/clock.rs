@@ -0,0 +1,254 @@
+use crate::js_bindings;
+
+/// Longest frame, in seconds, the game is stepped as. Longer gaps, such as
+/// while the page was hidden, would otherwise throw objects across the world.
+pub const MAX_DELTA_TIME: f32 = 0.25;
+
+/// A source of the current time in seconds, from an arbitrary start.
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+/// The browser's high resolution clock, `performance.now()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerformanceClock;
+
+impl Clock for PerformanceClock {
+    fn now(&self) -> f64 {
+        js_bindings::millis_now() / 1000.
+    }
+}
+
+/// A clock which only moves when told to, for tests. Clones share the same
+/// time, so one can be handed to a `FrameTimer` and another kept to move it.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    now: std::rc::Rc<std::cell::Cell<f64>>,
+}
+
+#[cfg(test)]
+impl VirtualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, secs: f64) {
+        self.now.set(self.now.get() + secs);
+    }
+}
+
+#[cfg(test)]
+impl Clock for VirtualClock {
+    fn now(&self) -> f64 {
+        self.now.get()
+    }
+}
+
+/// Measures the time between frames.
+pub struct FrameTimer {
+    clock: Box<dyn Clock>,
+    last_tick: Option<f64>,
+}
+
+impl FrameTimer {
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        Self {
+            clock: Box::new(clock),
+            last_tick: None,
+        }
+    }
+
+    /// Return the seconds since the last tick, at most `MAX_DELTA_TIME`.
+    /// The first tick returns zero.
+    pub fn tick(&mut self) -> f32 {
+        let now = self.clock.now();
+        let delta = self.last_tick.map_or(0., |last| (now - last) as f32);
+        self.last_tick = Some(now);
+        delta.clamp(0., MAX_DELTA_TIME)
+    }
+}
+
+/// Splits game time in to steps of a fixed length.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedTimestep {
+    fixed_delta_time: f32,
+    max_steps: u32,
+    /// Game time not yet stepped through.
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    /// Step every `fixed_delta_time` game seconds, running no more than
+    /// `max_steps` in one frame to catch up.
+    pub fn new(fixed_delta_time: f32, max_steps: u32) -> Self {
+        Self {
+            fixed_delta_time,
+            max_steps,
+            accumulator: 0.,
+        }
+    }
+
+    /// Add a frame's game time, returning how many fixed steps to run.
+    pub fn accumulate(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time;
+        let due = (self.accumulator / self.fixed_delta_time) as u32;
+        if due > self.max_steps {
+            // Too far behind to catch up. Drop the backlog rather than
+            // falling further behind every frame.
+            self.accumulator %= self.fixed_delta_time;
+            self.max_steps
+        } else {
+            self.accumulator -= due as f32 * self.fixed_delta_time;
+            due
+        }
+    }
+
+    pub fn get_fixed_delta_time(&self) -> f32 {
+        self.fixed_delta_time
+    }
+
+    /// How far the game is between the last fixed step and the next, from
+    /// 0 to 1, for interpolating what fixed steps move.
+    pub fn get_alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_delta_time).clamp(0., 1.)
+    }
+}
+
+/// Time as seen by steppables. Scaled time is game time, which runs
+/// faster or slower than real, unscaled time by the time scale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Time {
+    delta_time: f32,
+    unscaled_delta_time: f32,
+    elapsed: f64,
+    unscaled_elapsed: f64,
+    time_scale: f32,
+    fixed_delta_time: f32,
+    alpha: f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            delta_time: 0.,
+            unscaled_delta_time: 0.,
+            elapsed: 0.,
+            unscaled_elapsed: 0.,
+            time_scale: 1.,
+            fixed_delta_time: 0.,
+            alpha: 0.,
+        }
+    }
+}
+
+impl Time {
+    /// Move on to a frame `unscaled_delta` real seconds after the last.
+    pub fn advance(&mut self, unscaled_delta: f32) {
+        self.advance_by(unscaled_delta, unscaled_delta * self.time_scale);
+    }
+
+    /// Move on to a frame `unscaled_delta` real seconds and `delta` game
+    /// seconds after the last, ignoring the time scale.
+    pub fn advance_by(&mut self, unscaled_delta: f32, delta: f32) {
+        self.unscaled_delta_time = unscaled_delta;
+        self.unscaled_elapsed += unscaled_delta as f64;
+        self.delta_time = delta;
+        self.elapsed += delta as f64;
+    }
+
+    /// Record the fixed step timing for this frame.
+    pub fn set_fixed_step(&mut self, fixed_timestep: &FixedTimestep) -> &mut Self {
+        self.fixed_delta_time = fixed_timestep.get_fixed_delta_time();
+        self.alpha = fixed_timestep.get_alpha();
+        self
+    }
+
+    /// Game seconds since the last frame.
+    pub fn get_delta_time(&self) -> f32 {
+        self.delta_time
+    }
+
+    /// Real seconds since the last frame.
+    pub fn get_unscaled_delta_time(&self) -> f32 {
+        self.unscaled_delta_time
+    }
+
+    /// Game seconds since the game started.
+    pub fn get_elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Real seconds since the game started.
+    pub fn get_unscaled_elapsed(&self) -> f64 {
+        self.unscaled_elapsed
+    }
+
+    /// Game seconds each fixed step covers.
+    pub fn get_fixed_delta_time(&self) -> f32 {
+        self.fixed_delta_time
+    }
+
+    /// See `FixedTimestep::get_alpha`.
+    pub fn get_alpha(&self) -> f32 {
+        self.alpha
+    }
+
+    pub fn get_time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Set how many game seconds pass per real second, from the next frame.
+    pub fn set_time_scale(&mut self, time_scale: f32) -> &mut Self {
+        self.time_scale = time_scale.max(0.);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_follows_the_clock() {
+        let clock = VirtualClock::new();
+        let mut timer = FrameTimer::new(clock.clone());
+        let mut time = Time::default();
+        time.advance(timer.tick());
+        assert_eq!(time.get_delta_time(), 0.);
+
+        clock.advance(0.1);
+        time.advance(timer.tick());
+        assert!((time.get_delta_time() - 0.1).abs() < 1e-6);
+
+        time.set_time_scale(0.5);
+        clock.advance(0.2);
+        time.advance(timer.tick());
+        assert!((time.get_delta_time() - 0.1).abs() < 1e-6);
+        assert!((time.get_unscaled_delta_time() - 0.2).abs() < 1e-6);
+        assert!((time.get_elapsed() - 0.2).abs() < 1e-6);
+        assert!((time.get_unscaled_elapsed() - 0.3).abs() < 1e-6);
+
+        // Paused, so only real time passes.
+        clock.advance(0.2);
+        time.advance_by(timer.tick(), 0.);
+        assert_eq!(time.get_delta_time(), 0.);
+        assert!((time.get_elapsed() - 0.2).abs() < 1e-6);
+        assert!((time.get_unscaled_elapsed() - 0.5).abs() < 1e-6);
+
+        clock.advance(10.);
+        assert_eq!(timer.tick(), MAX_DELTA_TIME);
+    }
+
+    #[test]
+    fn fixed_steps_catch_up_to_a_limit() {
+        let mut fixed = FixedTimestep::new(0.25, 3);
+        assert_eq!(fixed.accumulate(0.1), 0);
+        assert!((fixed.get_alpha() - 0.4).abs() < 1e-6);
+        assert_eq!(fixed.accumulate(0.5), 2);
+        assert!((fixed.get_alpha() - 0.4).abs() < 1e-6);
+        assert_eq!(fixed.accumulate(2.), 3);
+        assert!(fixed.get_alpha() < 1.);
+        assert_eq!(fixed.accumulate(0.), 0);
+    }
+}
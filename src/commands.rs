@@ -0,0 +1,25 @@
+use crate::steppables::Steppable;
+use crate::transform::Transform;
+use crate::util::Rfc;
+use crate::world_object::{WorldObject3DInit, WorldObjectId};
+use crate::world_state::{ChildPolicy, SteppableId, WorldState};
+
+/// A structural change to the world, queued with `WorldState::defer` and
+/// applied by the game loop between step phases.
+pub enum WorldCommand {
+    /// Add an object, announced with an `ObjectSpawned` event.
+    Spawn(Box<WorldObject3DInit>),
+    SpawnPrefab {
+        name: String,
+        tf: Option<Box<Transform>>,
+    },
+    Despawn(WorldObjectId, ChildPolicy),
+    /// Add a global steppable under an id reserved by
+    /// `WorldState::defer_add_scripted_component`, and start it.
+    AddSteppable(SteppableId, Rfc<dyn Steppable<WorldState>>),
+    RemoveSteppable(SteppableId),
+    Reparent {
+        child: WorldObjectId,
+        parent: Option<WorldObjectId>,
+    },
+}
@@ -0,0 +1,263 @@
+use std::any::{Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::world_object::WorldObjectId;
+
+/// Every component of one type, keyed by the object it's attached to.
+struct Storage<T>(BTreeMap<WorldObjectId, T>);
+
+/// Lets `Components` drop an object's components without knowing their
+/// types.
+trait AnyStorage {
+    fn remove(&mut self, id: WorldObjectId);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove(&mut self, id: WorldObjectId) {
+        self.0.remove(&id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Converts one registered component type to and from scene data.
+#[derive(Clone, Copy)]
+struct ComponentSerde {
+    save: fn(&Components, WorldObjectId) -> Option<Result<Value, String>>,
+    load: fn(&mut Components, WorldObjectId, Value) -> Result<(), String>,
+}
+
+fn save_component<T: Serialize + 'static>(
+    components: &Components,
+    id: WorldObjectId,
+) -> Option<Result<Value, String>> {
+    components
+        .get::<T>(id)
+        .map(|c| serde_json::to_value(c).map_err(|e| e.to_string()))
+}
+
+fn load_component<T: DeserializeOwned + 'static>(
+    components: &mut Components,
+    id: WorldObjectId,
+    value: Value,
+) -> Result<(), String> {
+    let component: T = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    components.insert(id, component);
+    Ok(())
+}
+
+/// Typed data attached to world objects, at most one value per type per
+/// object.
+#[derive(Default)]
+pub struct Components {
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    /// Component types saved in scenes, by the name they're saved under.
+    registered: BTreeMap<String, ComponentSerde>,
+}
+
+impl Components {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&BTreeMap<WorldObjectId, T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|s| s.as_any().downcast_ref::<Storage<T>>())
+            .map(|s| &s.0)
+    }
+
+    fn existing_storage_mut<T: 'static>(&mut self) -> Option<&mut BTreeMap<WorldObjectId, T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|s| s.as_any_mut().downcast_mut::<Storage<T>>())
+            .map(|s| &mut s.0)
+    }
+
+    /// Storage for `T`, created if this is the first `T`.
+    fn storage_mut<T: 'static>(&mut self) -> &mut BTreeMap<WorldObjectId, T> {
+        let storage = self
+            .storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>(BTreeMap::new())));
+        &mut storage
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .expect("Component storage keyed by the wrong type")
+            .0
+    }
+
+    /// Attach a component, returning the one of the same type it replaced.
+    pub fn insert<T: 'static>(&mut self, id: WorldObjectId, component: T) -> Option<T> {
+        self.storage_mut().insert(id, component)
+    }
+
+    pub fn get<T: 'static>(&self, id: WorldObjectId) -> Option<&T> {
+        self.storage().and_then(|s| s.get(&id))
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, id: WorldObjectId) -> Option<&mut T> {
+        self.existing_storage_mut().and_then(|s| s.get_mut(&id))
+    }
+
+    pub fn remove<T: 'static>(&mut self, id: WorldObjectId) -> Option<T> {
+        self.existing_storage_mut().and_then(|s| s.remove(&id))
+    }
+
+    /// Drop every component attached to an object.
+    pub fn remove_all(&mut self, id: WorldObjectId) {
+        for storage in self.storages.values_mut() {
+            storage.remove(id);
+        }
+    }
+
+    /// Save and load components of type `T` in scenes, under `name`.
+    pub fn register<T: Serialize + DeserializeOwned + 'static>(&mut self, name: &str) {
+        self.registered.insert(
+            name.to_owned(),
+            ComponentSerde {
+                save: save_component::<T>,
+                load: load_component::<T>,
+            },
+        );
+    }
+
+    /// Return the registered components of an object, by registered name.
+    pub fn save_registered(&self, id: WorldObjectId) -> Result<BTreeMap<String, Value>, String> {
+        let mut saved = BTreeMap::new();
+        for (name, serde) in self.registered.iter() {
+            if let Some(value) = (serde.save)(self, id) {
+                saved.insert(name.to_owned(), value?);
+            }
+        }
+        Ok(saved)
+    }
+
+    /// Attach a component saved by `save_registered`.
+    pub fn load_registered(
+        &mut self,
+        id: WorldObjectId,
+        name: &str,
+        value: Value,
+    ) -> Result<(), String> {
+        let serde = *self
+            .registered
+            .get(name)
+            .ok_or(format!("Component {} is not registered", name))?;
+        (serde.load)(self, id, value).map_err(|e| format!("Component {}: {}", name, e))
+    }
+
+    /// Return every object having all of the components in `Q`, with
+    /// references to them, in id order.
+    pub fn query<'a, Q: Query<'a>>(&'a self) -> Vec<(WorldObjectId, Q::Item)> {
+        Q::candidates(self)
+            .into_iter()
+            .filter_map(|id| Q::fetch(self, id).map(|item| (id, item)))
+            .collect()
+    }
+}
+
+/// A tuple of component types to look up together, such as `(A, B)`.
+pub trait Query<'a> {
+    /// A tuple of references to each component.
+    type Item;
+
+    /// Objects which may match, taken from the first component type.
+    fn candidates(components: &'a Components) -> Vec<WorldObjectId>;
+
+    fn fetch(components: &'a Components, id: WorldObjectId) -> Option<Self::Item>;
+}
+
+macro_rules! impl_query {
+    ($first:ident $(, $rest:ident)*) => {
+        impl<'a, $first: 'static $(, $rest: 'static)*> Query<'a> for ($first, $($rest,)*) {
+            type Item = (&'a $first, $(&'a $rest,)*);
+
+            fn candidates(components: &'a Components) -> Vec<WorldObjectId> {
+                components
+                    .storage::<$first>()
+                    .map(|s| s.keys().copied().collect())
+                    .unwrap_or_default()
+            }
+
+            fn fetch(components: &'a Components, id: WorldObjectId) -> Option<Self::Item> {
+                Some((components.get::<$first>(id)?, $(components.get::<$rest>(id)?,)*))
+            }
+        }
+    };
+}
+
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    struct Health(u32);
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[test]
+    fn query_matches_objects_with_every_component() {
+        let mut components = Components::new();
+        components.insert(WorldObjectId(0), Health(10));
+        components.insert(WorldObjectId(1), Health(20));
+        components.insert(WorldObjectId(1), Name("one"));
+        components.insert(WorldObjectId(2), Name("two"));
+
+        let both = components.query::<(Health, Name)>();
+        assert_eq!(both, vec![(WorldObjectId(1), (&Health(20), &Name("one")))]);
+        assert_eq!(components.query::<(Name,)>().len(), 2);
+
+        components.get_mut::<Health>(WorldObjectId(0)).unwrap().0 = 5;
+        assert_eq!(components.get(WorldObjectId(0)), Some(&Health(5)));
+
+        components.remove_all(WorldObjectId(1));
+        assert_eq!(components.get::<Name>(WorldObjectId(1)), None);
+        assert_eq!(components.query::<(Health,)>().len(), 1);
+    }
+
+    #[test]
+    fn lookups_do_not_create_storage() {
+        let mut components = Components::new();
+        assert_eq!(components.get_mut::<Health>(WorldObjectId(0)), None);
+        assert_eq!(components.remove::<Health>(WorldObjectId(0)), None);
+        assert!(components.storages.is_empty());
+    }
+
+    #[test]
+    fn registered_components_round_trip() {
+        let mut components = Components::new();
+        components.register::<Health>("health");
+        components.insert(WorldObjectId(0), Health(7));
+        components.insert(WorldObjectId(0), Name("unregistered"));
+
+        let saved = components.save_registered(WorldObjectId(0)).unwrap();
+        assert_eq!(saved.len(), 1);
+        for (name, value) in saved {
+            components
+                .load_registered(WorldObjectId(3), &name, value)
+                .unwrap();
+        }
+        assert_eq!(components.get(WorldObjectId(3)), Some(&Health(7)));
+        assert!(components
+            .load_registered(WorldObjectId(3), "name", Value::Null)
+            .is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::any::{type_name, Any, TypeId};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::steppables::StepError;
+use crate::util::Rfc;
+use crate::world_object::WorldObjectId;
+
+/// Published when a world object is added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectSpawned(pub WorldObjectId);
+
+/// Published for every world object removed, including removed children.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectDestroyed(pub WorldObjectId);
+
+/// Published when the mouse moves over the page.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MouseMoved {
+    pub view_x: f32,
+    pub view_y: f32,
+}
+
+/// Handle to a subscription, for unsubscribing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+/// A subscriber with the event type erased. Only called with events of
+/// the type it subscribed to.
+pub type Handler<S> = Rfc<dyn FnMut(&dyn Any, &mut S) -> Result<(), StepError<String>>>;
+
+/// An event and the subscribers to hand it to.
+pub struct Delivery<S> {
+    pub event: Box<dyn Any>,
+    /// Type name of the event, to report subscriber errors under.
+    pub event_name: &'static str,
+    pub handlers: Vec<(SubscriptionId, Handler<S>)>,
+}
+
+/// Events queued by type, handed to subscribers of that type when
+/// delivered.
+pub struct EventBus<S> {
+    queued: Vec<(TypeId, &'static str, Box<dyn Any>)>,
+    subscribers: HashMap<TypeId, Vec<(SubscriptionId, Handler<S>)>>,
+    next_id: usize,
+}
+
+impl<S> EventBus<S> {
+    pub fn new() -> Self {
+        Self {
+            queued: vec![],
+            subscribers: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Queue an event until the next delivery.
+    pub fn publish<T: 'static>(&mut self, event: T) {
+        self.queued
+            .push((TypeId::of::<T>(), type_name::<T>(), Box::new(event)));
+    }
+
+    pub fn subscribe<T: 'static>(
+        &mut self,
+        mut handler: impl FnMut(&T, &mut S) -> Result<(), StepError<String>> + 'static,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        let erased: Handler<S> =
+            Rc::new(RefCell::new(
+                move |event: &dyn Any, state: &mut S| match event.downcast_ref::<T>() {
+                    Some(event) => handler(event, state),
+                    None => Ok(()),
+                },
+            ));
+        self.subscribers
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push((id, erased));
+        id
+    }
+
+    /// Returns false if the subscription was already gone.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        for handlers in self.subscribers.values_mut() {
+            if let Some(pos) = handlers.iter().position(|(h_id, _)| *h_id == id) {
+                handlers.remove(pos);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Empty the queue, pairing each event with its current subscribers
+    /// in publish order. Events without subscribers are dropped.
+    pub fn take_deliveries(&mut self) -> Vec<Delivery<S>> {
+        let queued = std::mem::take(&mut self.queued);
+        queued
+            .into_iter()
+            .filter_map(|(type_id, event_name, event)| {
+                let handlers = self.subscribers.get(&type_id)?.clone();
+                Some(Delivery {
+                    event,
+                    event_name,
+                    handlers,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deliver(bus: &mut EventBus<Vec<String>>, log: &mut Vec<String>) {
+        for delivery in bus.take_deliveries() {
+            for (_, handler) in delivery.handlers {
+                (handler.borrow_mut())(&*delivery.event, log)
+                    .or_else(|e| e.translate())
+                    .unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn events_reach_subscribers_of_their_type() {
+        let mut bus: EventBus<Vec<String>> = EventBus::new();
+        let mut log = vec![];
+        let spawned = bus.subscribe(|e: &ObjectSpawned, log: &mut Vec<String>| {
+            log.push(format!("spawned {}", (e.0).0));
+            Ok(())
+        });
+        bus.subscribe(|e: &ObjectDestroyed, log: &mut Vec<String>| {
+            log.push(format!("destroyed {}", (e.0).0));
+            Ok(())
+        });
+
+        bus.publish(ObjectSpawned(WorldObjectId(1)));
+        bus.publish(ObjectDestroyed(WorldObjectId(1)));
+        bus.publish(7u32);
+        assert!(log.is_empty());
+        deliver(&mut bus, &mut log);
+        assert_eq!(log, vec!["spawned 1", "destroyed 1"]);
+
+        assert!(bus.unsubscribe(spawned));
+        assert!(!bus.unsubscribe(spawned));
+        bus.publish(ObjectSpawned(WorldObjectId(2)));
+        deliver(&mut bus, &mut log);
+        assert_eq!(log.len(), 2);
+    }
+}
@@ -0,0 +1,143 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::js_bindings;
+
+/// Animation frames arrive a little early or late, so a frame this close to
+/// being due still counts.
+const FRAME_SLACK_MS: f64 = 1.0;
+
+type FrameCallback = Closure<dyn FnMut(f64)>;
+
+/// Picks which animation frames to step on under a frame rate cap.
+struct FrameLimiter {
+    min_interval_ms: f64,
+    last_ms: Option<f64>,
+}
+
+impl FrameLimiter {
+    fn new(frame_cap: Option<f32>) -> Self {
+        Self {
+            min_interval_ms: frame_cap.map_or(0., |cap| 1000. / cap as f64),
+            last_ms: None,
+        }
+    }
+
+    /// Return whether to step on a frame arriving at `now_ms`.
+    fn is_due(&mut self, now_ms: f64) -> bool {
+        match self.last_ms {
+            Some(last) if now_ms - last < self.min_interval_ms - FRAME_SLACK_MS => false,
+            // Keep to the cap's schedule rather than the display's, unless
+            // too far behind to catch up.
+            Some(last) if now_ms - last < 2. * self.min_interval_ms => {
+                self.last_ms = Some(last + self.min_interval_ms);
+                true
+            }
+            _ => {
+                self.last_ms = Some(now_ms);
+                true
+            }
+        }
+    }
+
+    /// Step on the next frame, whenever it comes.
+    fn reset(&mut self) {
+        self.last_ms = None;
+    }
+}
+
+/// Stops a loop started by `run_frame_loop`. Clones control the same loop.
+#[derive(Clone)]
+pub struct LoopHandle {
+    stopped: Rc<Cell<bool>>,
+    /// Id of the requested animation frame, if one is pending.
+    pending: Rc<Cell<Option<i32>>>,
+    callback: Rc<RefCell<Option<FrameCallback>>>,
+}
+
+impl LoopHandle {
+    /// Stop requesting frames. A frame in progress still finishes.
+    pub fn stop(&self) {
+        self.stopped.set(true);
+        if let Some(id) = self.pending.take() {
+            js_bindings::cancel_animation_frame(id);
+        }
+        // The callback holds a handle, so drop it to free both.
+        self.callback.borrow_mut().take();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.get()
+    }
+
+    fn request_frame(&self) -> Result<(), String> {
+        if let Some(callback) = self.callback.borrow().as_ref() {
+            let id = js_bindings::request_animation_frame(callback)?;
+            self.pending.set(Some(id));
+        }
+        Ok(())
+    }
+}
+
+/// Call `frame` on animation frames, at most `frame_cap` times a second,
+/// until it fails or the returned handle is stopped. Failures are logged.
+///
+/// Browsers stop sending animation frames to hidden pages. Any which still
+/// arrive while hidden are skipped.
+pub fn run_frame_loop(
+    frame_cap: Option<f32>,
+    mut frame: impl FnMut() -> Result<(), String> + 'static,
+) -> Result<LoopHandle, String> {
+    let handle = LoopHandle {
+        stopped: Rc::new(Cell::new(false)),
+        pending: Rc::new(Cell::new(None)),
+        callback: Rc::new(RefCell::new(None)),
+    };
+    let mut limiter = FrameLimiter::new(frame_cap);
+    let loop_handle = handle.clone();
+    let callback = Closure::wrap(Box::new(move |now_ms: f64| {
+        loop_handle.pending.set(None);
+        if loop_handle.is_stopped() {
+            return;
+        }
+        if js_bindings::page_hidden() {
+            limiter.reset();
+        } else if limiter.is_due(now_ms) {
+            if let Err(e) = frame() {
+                js_bindings::error(&e);
+                loop_handle.stop();
+                return;
+            }
+        }
+        if let Err(e) = loop_handle.request_frame() {
+            js_bindings::error(&e);
+            loop_handle.stop();
+        }
+    }) as Box<dyn FnMut(f64)>);
+    *handle.callback.borrow_mut() = Some(callback);
+    handle.request_frame()?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps_in_a_second(frame_cap: Option<f32>, display_hz: f64) -> usize {
+        let mut limiter = FrameLimiter::new(frame_cap);
+        (0..display_hz as usize)
+            .filter(|&i| limiter.is_due(i as f64 * 1000. / display_hz))
+            .count()
+    }
+
+    #[test]
+    fn frames_are_capped() {
+        assert_eq!(steps_in_a_second(None, 144.), 144);
+        assert_eq!(steps_in_a_second(Some(60.), 60.), 60);
+        let capped = steps_in_a_second(Some(60.), 144.);
+        assert!((58..=60).contains(&capped), "{} steps", capped);
+        assert_eq!(steps_in_a_second(Some(60.), 30.), 30);
+    }
+}
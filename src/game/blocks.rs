@@ -2,17 +2,18 @@ use std::sync::{Arc, Mutex};
 
 use na::Vector3;
 
+use crate::game::spinning::Spin;
 use crate::maths_utils;
-use crate::mesh;
+use crate::maths_utils::EulerAngles3D;
+use crate::prefab::{Prefab, PrefabMesh};
 use crate::rendering;
 use crate::rendering::ProgramData;
 use crate::rendering::RenderLineBuilder;
+use crate::scene::MeshRef;
 use crate::steppables::StepError;
 use crate::steppables::Steppable;
 use crate::transform::Transform;
-use crate::world_object::{
-    Material, MeshComponent, RenderComponent, WorldObject3DInit, WorldObjectId,
-};
+use crate::world_object::{Material, WorldObject3DInit, WorldObjectId};
 use crate::world_state::WorldState;
 
 struct MovingBlockBehavior {
@@ -21,11 +22,17 @@ struct MovingBlockBehavior {
 
 const GRID_SIZE: f32 = 1.0;
 const BLOCK_SIZE: f32 = 0.3;
+const BLOCK_PREFAB: &str = "block";
+/// Degrees per second each block turns about its z axis.
+const BLOCK_SPIN: f32 = 45.;
 
 #[derive(Debug, Clone)]
 pub struct BlockBehavior {
     pub program_data: ProgramData,
+    pub instanced_program_data: ProgramData,
     pub mesh_data: String,
+    /// Where the mesh came from, so blocks can be saved in scenes.
+    pub mesh_ref: MeshRef,
 }
 
 impl BlockBehavior {
@@ -50,6 +57,23 @@ impl BlockBehavior {
                     .ok_or("Could not create VAO")?,
             );
 
+        let mut block = PrefabMesh::from_obj(
+            mesh_data,
+            program_data.clone(),
+            Material {
+                color: (1.0, 0.6, 1.0, 1.0),
+                ..Default::default()
+            },
+        )?;
+        block.instanced_program_data = Some(self.instanced_program_data.clone());
+        block.mesh_ref = Some(self.mesh_ref.clone());
+        ws.register_prefab(
+            BLOCK_PREFAB,
+            Prefab {
+                mesh: Some(block),
+                ..Default::default()
+            },
+        );
         for i in 0..width {
             for j in 0..length {
                 let mut tf = Transform::identity();
@@ -58,23 +82,8 @@ impl BlockBehavior {
                 let z_pos = 0.;
                 tf.set_position(Vector3::new(x_pos, y_pos, z_pos));
                 tf.set_scale(Vector3::new(BLOCK_SIZE, BLOCK_SIZE, BLOCK_SIZE));
-                ws.add_world_obj(
-                    WorldObject3DInit {
-                        tf,
-                        mesh: Some(MeshComponent {
-                            data: mesh::wavefront_obj::into_vertex_vec(mesh_data).unwrap(),
-                        }),
-                        render: Some(RenderComponent {
-                            gl_program_data: program_data.clone(),
-                            renderer: ws.get_renderer().ok_or("Failed to make blocks")?,
-                            material: Material {
-                                color: (1.0, 0.6, 1.0, 1.0),
-                            },
-                        }),
-                        ..Default::default()
-                    }
-                    .init(),
-                );
+                let block = ws.instantiate_prefab(BLOCK_PREFAB, Some(tf))?;
+                ws.add_component(block, Spin(EulerAngles3D::from_deg(0., 0., BLOCK_SPIN)))?;
 
                 line_builder = add_line(
                     Vector3::new(x_pos, y_pos, z_pos),
@@ -1,72 +1,48 @@
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeMap;
 
-use crate::mesh::wavefront_obj::{get_mesh_data_from_url, into_vertex_vec};
-use crate::spin::GameLoop;
+use na::Vector3;
+
+use crate::maths_utils::EulerAngles3D;
+use crate::mesh::wavefront_mtl::into_materials;
+use crate::mesh::wavefront_obj::into_mesh_set;
+use crate::rendering::ProgramData;
+use crate::scene::MeshRef;
 use crate::steppables::{StepError, Steppable};
-use crate::world_object::{
-    Material, MeshComponent, RenderComponent, WorldObject3D, WorldObject3DInit,
-};
+use crate::transform::Transform;
 use crate::world_state::WorldState;
 
-impl Steppable<WorldState> for CottageMaker {
-    fn start(&mut self, _state: &mut WorldState) -> Result<(), StepError<String>> {
-        self.mesh_string_data = get_mesh_data_from_url("assets/cottage_1.obj".into());
-        Ok(())
-    }
-
-    fn step(&mut self, state: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
-        if !self.made {
-            if let Ok(mesh_lock) = self.mesh_string_data.try_lock() {
-                if let Some(mesh_data) = mesh_lock.as_ref() {
-                    let renderer = state
-                        .get_renderer()
-                        .ok_or(StepError::Recover("Failed to get renderer".into()))?;
-                    state.add_world_obj(
-                        WorldObject3DInit {
-                            mesh: Some(MeshComponent {
-                                data: into_vertex_vec(mesh_data)
-                                    .expect("Could not parse wavefront obj data"),
-                            }),
-                            render: Some(RenderComponent {
-                                renderer,
-                                material: Material {
-                                    color: (0.5, 0.5, 0.5, 1.0),
-                                },
-                                gl_program_data: panic!(),
-                            }),
-                            ..Default::default()
-                        }
-                        .init(),
-                    );
-                    self.made = true;
-                }
-            }
-        }
-        Ok(())
-    }
+/// Places the cottage model in the world, one object per mesh object.
+#[derive(Debug, Clone)]
+pub struct CottageMaker {
+    pub program_data: ProgramData,
+    pub mesh_data: String,
+    /// Contents of the mesh's material library, if it has one.
+    pub material_data: Option<String>,
+    /// Where the mesh came from, so the cottage can be saved in scenes.
+    pub mesh_ref: MeshRef,
 }
 
-struct CottageMaker {
-    made: bool,
-    mesh_string_data: Arc<Mutex<Option<String>>>,
-}
-
-impl Clone for CottageMaker {
-    fn clone(&self) -> Self {
-        Self {
-            made: false,
-            mesh_string_data: self.mesh_string_data.clone(),
-        }
+impl Steppable<WorldState> for CottageMaker {
+    fn start(&mut self, state: &mut WorldState) -> Result<(), StepError<String>> {
+        let materials = match &self.material_data {
+            Some(data) => into_materials(data).map_err(StepError::Recover)?,
+            None => BTreeMap::new(),
+        };
+        let mesh_set = into_mesh_set(&self.mesh_data).map_err(StepError::Recover)?;
+        let mut tf = Transform::identity();
+        tf.set_position(Vector3::new(-12., -12., -2.));
+        // The model is z-up, and the camera looks down +z.
+        tf.set_euler_rotation(EulerAngles3D::from_deg(180., 0., 0.));
+        state
+            .add_mesh_set(
+                "cottage",
+                mesh_set,
+                &materials,
+                &self.program_data,
+                &tf,
+                Some(self.mesh_ref.clone()),
+            )
+            .map_err(StepError::Recover)?;
+        Ok(())
     }
 }
-
-/*
-pub fn make_cottage(ws: &mut WorldState) -> Result<(), String> {
-    let renderer = ws.get_renderer().expect("Could not get renderer");
-    ws.add_world_obj(WorldObject3DInit {
-        mesh: get_mesh_data_from_url("assets/cottage_1.obj"),
-        ..Default::default()
-    }.init());
-    Ok(())
-}
-*/
@@ -1,3 +1,4 @@
 pub mod blocks;
 pub mod rotate_with_mouse;
 pub mod cottage_scene;
+pub mod spinning;
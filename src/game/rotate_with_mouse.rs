@@ -1,8 +1,9 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use na::{UnitQuaternion, Vector3};
+
 use crate::inputs::Input;
-use crate::maths_utils::EulerAngles3D;
 use crate::spin::GameLoop;
 use crate::steppables::{StepError, Steppable};
 use crate::transform::Transform;
@@ -21,9 +22,14 @@ impl Steppable<WorldState> for RotateWithMouse {
                     .tf
                     .try_borrow_mut()
                     .map_err(|_| StepError::Fatal("Could not borrow".into()))?;
-                let x = -inputs.get_mouse_view_x() * 360.;
-                let y = -inputs.get_mouse_view_y() * 180.;
-                tf.set_euler_rotation(EulerAngles3D::from_deg(y, 0., x));
+                let x = (-inputs.get_mouse_view_x() * 360.).to_radians();
+                let y = (-inputs.get_mouse_view_y() * 180.).to_radians();
+                // Turn around the world z axis, then tilt around the
+                // turned x axis.
+                tf.set_rotation(
+                    UnitQuaternion::from_axis_angle(&Vector3::z_axis(), x)
+                        * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), y),
+                );
                 Ok(())
             }
             _ => Err(StepError::Recover("Could not get inputs".into())),
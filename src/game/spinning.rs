@@ -0,0 +1,57 @@
+use na::UnitQuaternion;
+
+use crate::maths_utils::EulerAngles3D;
+use crate::spin::GameLoop;
+use crate::steppables::{StepError, Steppable};
+use crate::world_state::WorldState;
+
+/// Component spinning an object at a constant rate per second.
+#[derive(Debug, Clone)]
+pub struct Spin(pub EulerAngles3D<f32>);
+
+/// Turns every object with a `Spin` component.
+#[derive(Debug, Clone)]
+pub struct Spinner;
+
+impl Steppable<WorldState> for Spinner {
+    fn step(&mut self, state: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
+        let delta_time = state.get_time().get_delta_time();
+        for (id, (spin,)) in state.query::<(Spin,)>() {
+            if let Some(obj) = state.get_world_obj(&id) {
+                obj.tf_rc
+                    .borrow_mut()
+                    .rotate_by(UnitQuaternion::from_euler_angles(
+                        spin.0.roll.as_rad() * delta_time,
+                        spin.0.pitch.as_rad() * delta_time,
+                        spin.0.yaw.as_rad() * delta_time,
+                    ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector3;
+
+    #[test]
+    fn spins_only_objects_with_spin() {
+        let mut state = WorldState::new();
+        let spun = state.new_world_obj();
+        let still = state.new_world_obj();
+        state
+            .add_component(spun, Spin(EulerAngles3D::from_deg(0., 0., 90.)))
+            .unwrap();
+        state.get_time_mut().advance(0.5);
+
+        assert!(Spinner.step(&mut state, &GameLoop::empty()).is_ok());
+
+        let turned = state.get_world_obj(&spun).unwrap().get_local_tf();
+        let expected = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), 45f32.to_radians());
+        assert!(turned.get_rotation().angle_to(&expected) < 1e-5);
+        let unturned = state.get_world_obj(&still).unwrap().get_local_tf();
+        assert_eq!(unturned.get_rotation(), UnitQuaternion::identity());
+    }
+}
@@ -2,6 +2,7 @@ use std::future::Future;
 
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
 use web_sys::window;
 
 use crate::inputs;
@@ -37,42 +38,76 @@ impl inputs::Input for InputBinding {
     }
 }
 
-/// Print a log to the JS Console.
+/// Print a log to the JS Console, or stdout outside the browser.
 pub fn log(s: &str) {
+    #[cfg(target_arch = "wasm32")]
     web_sys::console::log_1(&s.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    println!("{}", s);
 }
 
-/// Print an error to the JS Console.
+/// Print an error to the JS Console, or stderr outside the browser.
 pub fn error(s: &str) {
+    #[cfg(target_arch = "wasm32")]
     web_sys::console::error_1(&s.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", s);
 }
 
-/// Print an error to the JS Console.
+/// Print a warning to the JS Console, or stderr outside the browser.
 pub fn warn(s: &str) {
+    #[cfg(target_arch = "wasm32")]
     web_sys::console::warn_1(&s.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", s);
 }
 
-pub fn millis_now() -> u32 {
-    js_sys::Date::new_0().get_utc_milliseconds()
+/// Milliseconds since the page loaded, from `performance.now()`.
+pub fn millis_now() -> f64 {
+    match window().and_then(|w| w.performance()) {
+        Some(performance) => performance.now(),
+        None => js_sys::Date::now(),
+    }
 }
 
-pub type IntervalType = (Closure<dyn FnMut()>, i32);
+/// Resolve after `time_ms`, letting the browser run in the meantime.
+pub async fn sleep(time_ms: i32) -> Result<(), String> {
+    let window = window().ok_or("No global `window` exists. Exiting.")?;
+    let mut timeout_result = Ok(0);
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        timeout_result =
+            window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, time_ms);
+    });
+    timeout_result.map_err(|_| "Unable to set timeout".to_string())?;
+    JsFuture::from(promise)
+        .await
+        .map(|_| ())
+        .map_err(|_| "Timeout was rejected".into())
+}
 
-pub fn ergonomic_interval(time_ms: i32, closure: impl FnMut() + 'static) -> Result<IntervalType, String> {
+/// Call `callback` before the next repaint, returning the request's id.
+pub fn request_animation_frame(callback: &Closure<dyn FnMut(f64)>) -> Result<i32, String> {
     let window = window().ok_or("No global `window` exists. Exiting.")?;
-    let closure = Closure::wrap(Box::new(closure) as Box<dyn FnMut()>);
-    let r = window.set_interval_with_callback_and_timeout_and_arguments_0(
-        closure.as_ref().unchecked_ref(),
-        time_ms,
-    );
-    match r {
-        Ok(handle) => Ok((closure, handle)),
-        Err(e) => {
+    window
+        .request_animation_frame(callback.as_ref().unchecked_ref())
+        .map_err(|e| {
             let e_string: String = e
                 .as_string()
                 .unwrap_or_else(|| "[Unable to display error]".into());
-            Err(format!("Unable to make interval: {}", &e_string))
-        }
+            format!("Unable to request animation frame: {}", &e_string)
+        })
+}
+
+pub fn cancel_animation_frame(id: i32) {
+    if let Some(window) = window() {
+        // Only fails for ids which were never requested.
+        window.cancel_animation_frame(id).ok();
     }
 }
 
+/// Whether the page is in a background tab or otherwise out of sight.
+pub fn page_hidden() -> bool {
+    window()
+        .and_then(|w| w.document())
+        .is_some_and(|d| d.hidden())
+}
@@ -10,24 +10,32 @@ extern crate wasm_bindgen;
 extern crate wasm_bindgen_futures;
 extern crate web_sys;
 
-use once_cell::sync::Lazy;
-use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::rc::Rc;
-use std::sync::{Arc, Mutex};
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 use web_sys::*;
 
-use js_bindings::{ergonomic_interval, IntervalType};
-
+mod camera;
+mod clock;
+mod commands;
+mod component;
+mod events;
+mod frame_loop;
 mod geometry;
 mod inputs;
 mod js_bindings;
+mod lighting;
 mod maths_utils;
 mod mesh;
+mod object_index;
+mod prefab;
 mod rendering;
 mod rigidbody;
+mod scene;
 mod spin;
 mod steppables;
+mod texture;
 mod transform;
 #[macro_use]
 mod util;
@@ -36,7 +44,8 @@ mod shader_config;
 mod world_object;
 mod world_state;
 
-const FRAME_RATE_CAP: f32 = 60.0;
+const FRAME_RATE_CAP: Option<f32> = Some(60.0);
+const LOAD_IN_POLL_MS: i32 = 50;
 
 #[wasm_bindgen]
 pub fn get_transform_mat(x: f32, y: f32) -> Vec<f32> {
@@ -44,39 +53,88 @@ pub fn get_transform_mat(x: f32, y: f32) -> Vec<f32> {
     v.as_slice().to_vec()
 }
 
-static WORLD_KILLER: Lazy<Mutex<Box<dyn (Fn()) + Sync + Send>>> =
-    Lazy::new(|| Mutex::new(Box::new(|| {})));
-static EXIT_ERROR: Lazy<Mutex<Option<String>>> = Lazy::new(Mutex::default);
-
 #[wasm_bindgen]
 pub struct PreloadedGameData {
 
 }
 
+/// Controls a running game from the page.
+#[wasm_bindgen]
+pub struct GameHandle {
+    game_loop: Rc<RefCell<spin::GameLoop>>,
+    frame_loop: frame_loop::LoopHandle,
+}
+
+#[wasm_bindgen]
+impl GameHandle {
+    /// Stop stepping and drawing the game for good.
+    pub fn stop(&self) {
+        self.frame_loop.stop();
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.frame_loop.is_stopped()
+    }
+
+    /// Stop stepping the game, while still drawing it.
+    pub fn pause(&self) {
+        self.game_loop.borrow_mut().pause();
+    }
+
+    pub fn resume(&self) {
+        self.game_loop.borrow_mut().resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.game_loop.borrow().is_paused()
+    }
+
+    /// While paused, step through the next frame.
+    pub fn step_once(&self) {
+        self.game_loop.borrow_mut().step_once();
+    }
+
+    /// Set how many game seconds pass per real second.
+    pub fn set_time_scale(&self, time_scale: f32) {
+        self.game_loop.borrow_mut().set_time_scale(time_scale);
+    }
+
+    pub fn get_time_scale(&self) -> f32 {
+        self.game_loop.borrow().get_time_scale()
+    }
+}
+
 #[wasm_bindgen]
 pub async fn bind_game(
     context: web_sys::WebGl2RenderingContext,
-    program: WebGlProgram,
+    programs: js_sys::Object,
     u_location_names: JsValue,
     canvas_elem: HtmlCanvasElement,
     preloaded_game_data: PreloadedGameData,
-) -> String {
+) -> Result<GameHandle, JsValue> {
     // Useful for debugging.
     set_panic_hook();
-    match bootstrap(context, program, canvas_elem) {
-        Err(e) => {
+    bootstrap(context, programs, canvas_elem)
+        .await
+        .map_err(|e| {
             js_bindings::error(&e);
-            e
-        }
-        _ => "Ok".into(),
-    }
+            e.into()
+        })
+}
+
+/// Pull a linked program out of the JS object mapping names to programs.
+fn get_program(programs: &js_sys::Object, name: &str) -> Result<WebGlProgram, String> {
+    js_sys::Reflect::get(programs, &name.into())
+        .ok()
+        .and_then(|p| p.dyn_into::<WebGlProgram>().ok())
+        .ok_or(format!("No WebGl program named {}", name))
 }
 
-fn bootstrap(
+async fn bootstrap(
     context: web_sys::WebGl2RenderingContext,
-    program: WebGlProgram,
+    programs: js_sys::Object,
     canvas_elem: HtmlCanvasElement,
-) -> Result<(), String> {
+) -> Result<GameHandle, String> {
     // Get list of locations as a vector.
     /*
     let u_location_names: Vec<String> = serde_wasm_bindgen::from_value(u_location_names)
@@ -84,49 +142,75 @@ fn bootstrap(
     */
     let mut game_loop: spin::GameLoop = spin::GameLoop::empty();
     let ctx_rc = Rc::new(context);
+    game_loop.set_projection_mode(spin::ProjectionMode::Perspective);
     game_loop.bind_canvas(canvas_elem, ctx_rc);
     game_loop.add_load_in_mesh("assets/cube.obj".into());
     game_loop.add_load_in_mesh("assets/cottage_1.obj".into());
     game_loop.add_gl_program(
         "vertex_color".into(),
-        program,
+        get_program(&programs, "vertex_color")?,
         &[
             "u_transformationMatrix".into(),
             "a_position".into(),
             "a_color".into(),
         ],
     );
-    game_loop.load_in();
+    game_loop.add_gl_program(
+        "instanced_color".into(),
+        get_program(&programs, "instanced_color")?,
+        &[
+            "u_transformationMatrix".into(),
+            "a_position".into(),
+            "a_instanceMatrix".into(),
+            "a_instanceColor".into(),
+        ],
+    );
+    let lit_locations = |extra: &[&str]| -> Vec<String> {
+        extra
+            .iter()
+            .chain(lighting::LIGHTING_UNIFORMS.iter())
+            .map(|&n| n.to_owned())
+            .collect()
+    };
+    game_loop.add_gl_program(
+        "lit".into(),
+        get_program(&programs, "lit")?,
+        &lit_locations(&[
+            "u_transformationMatrix",
+            "u_model",
+            "u_normalMatrix",
+            "a_position",
+            "a_normal",
+            "a_color",
+            "a_texcoord",
+            texture::DIFFUSE_TEXTURE_UNIFORM,
+        ]),
+    );
+    game_loop.add_gl_program(
+        "instanced_lit".into(),
+        get_program(&programs, "instanced_lit")?,
+        &lit_locations(&[
+            "u_transformationMatrix",
+            "a_position",
+            "a_normal",
+            "a_instanceMatrix",
+            "a_instanceColor",
+            texture::DIFFUSE_TEXTURE_UNIFORM,
+        ]),
+    );
+    // Setup reads the loaded assets, so wait for all of them first.
+    while !game_loop.load_in()? {
+        js_bindings::sleep(LOAD_IN_POLL_MS).await?;
+    }
     game_loop.setup()?;
     game_loop.start()?;
-    let (closure, handle) = recursive_loop(game_loop)?;
-    let mut killer = WORLD_KILLER.lock().unwrap();
-    *killer = Box::new(move || {
-        let window = window().expect("No global `window` exists. Unable to kill closure.");
-        window.clear_interval_with_handle(handle);
-        let exit_space = EXIT_ERROR.lock().unwrap();
-        if let Some(x) = exit_space.borrow().as_ref() {
-            js_bindings::error(x)
-        }
-    });
-    closure.forget();
-    Ok(())
-}
-
-fn recursive_loop(game_loop: spin::GameLoop) -> Result<IntervalType, String> {
-    let game_loop = Arc::new(Mutex::new(game_loop));
-    ergonomic_interval((1000.0 / FRAME_RATE_CAP) as i32, move || {
-        let stepped = game_loop.clone().lock().unwrap().step();
-        if let Err(e) = stepped {
-            {
-                // Set the exit error to print out.
-                let mut exit_space = EXIT_ERROR.lock().unwrap();
-                *exit_space = Some(e);
-            }
-            // Kill the spawned thread.
-            let killer = WORLD_KILLER.lock().unwrap();
-            killer.borrow()();
-        }
+    let game_loop = Rc::new(RefCell::new(game_loop));
+    let stepped_loop = game_loop.clone();
+    let frame_loop =
+        frame_loop::run_frame_loop(FRAME_RATE_CAP, move || stepped_loop.borrow_mut().step())?;
+    Ok(GameHandle {
+        game_loop,
+        frame_loop,
     })
 }
 
@@ -138,7 +222,5 @@ pub fn set_panic_hook() {
     // For more details see
     // https://github.com/rustwasm/console_error_panic_hook#readme
     //#[cfg(feature = "console_error_panic_hook")]
-    let killer = WORLD_KILLER.lock().unwrap();
-    killer.borrow()();
     console_error_panic_hook::set_once();
 }
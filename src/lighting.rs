@@ -0,0 +1,90 @@
+use na::Vector3;
+
+/// Must match `MAX_DIR_LIGHTS` in `lit_fs.glsl`.
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 4;
+/// Must match `MAX_POINT_LIGHTS` in `lit_fs.glsl`.
+pub const MAX_POINT_LIGHTS: usize = 4;
+
+/// Uniforms read by the lit shaders, for registering lit programs.
+pub const LIGHTING_UNIFORMS: &[&str] = &[
+    "u_cameraPosition",
+    "u_materialAmbient",
+    "u_materialSpecular",
+    "u_materialShininess",
+    "u_ambientLight",
+    "u_dirLightCount",
+    "u_dirLightDirections",
+    "u_dirLightColors",
+    "u_pointLightCount",
+    "u_pointLightPositions",
+    "u_pointLightColors",
+    "u_pointLightRanges",
+];
+
+/// Light arriving from the same direction everywhere, like the sun.
+#[derive(Debug, Clone)]
+pub struct DirectionalLight {
+    /// Direction the light travels in, in world space.
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+/// Light radiating out from a position, fading to nothing at `range`.
+#[derive(Debug, Clone)]
+pub struct PointLight {
+    pub position: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+    pub range: f32,
+}
+
+/// Every light in the world, uploaded to lit programs each frame.
+#[derive(Debug, Clone)]
+pub struct Lighting {
+    pub ambient: Vector3<f32>,
+    pub directional: Vec<DirectionalLight>,
+    pub point: Vec<PointLight>,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: Vector3::new(0.3, 0.3, 0.3),
+            directional: vec![DirectionalLight {
+                direction: Vector3::new(0.3, 0.5, 1.0),
+                color: Vector3::new(1., 1., 1.),
+                intensity: 0.7,
+            }],
+            point: vec![],
+        }
+    }
+}
+
+impl Lighting {
+    /// Flattened (directions, colours) of the directional lights the
+    /// shaders can handle. Colours are pre-multiplied by intensity.
+    pub fn directional_uniform_data(&self) -> (Vec<f32>, Vec<f32>) {
+        let mut directions = vec![];
+        let mut colors = vec![];
+        for light in self.directional.iter().take(MAX_DIRECTIONAL_LIGHTS) {
+            directions.extend(light.direction.normalize().iter());
+            colors.extend((light.color * light.intensity).iter());
+        }
+        (directions, colors)
+    }
+
+    /// Flattened (positions, colours, ranges) of the point lights the
+    /// shaders can handle. Colours are pre-multiplied by intensity.
+    pub fn point_uniform_data(&self) -> (Vec<f32>, Vec<f32>, Vec<f32>) {
+        let mut positions = vec![];
+        let mut colors = vec![];
+        let mut ranges = vec![];
+        for light in self.point.iter().take(MAX_POINT_LIGHTS) {
+            positions.extend(light.position.iter());
+            colors.extend((light.color * light.intensity).iter());
+            ranges.push(light.range);
+        }
+        (positions, colors, ranges)
+    }
+}
@@ -1 +1,140 @@
+use std::collections::HashMap;
+
+use na::Vector3;
+
+pub mod triangulate;
+pub mod wavefront_mtl;
 pub mod wavefront_obj;
+
+/// Vertex data with duplicate vertices removed, to be drawn through an
+/// index buffer.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexedMesh {
+    /// Flattened xyz vertex positions.
+    pub positions: Vec<f32>,
+    /// Flattened xyz vertex normals, one per position.
+    pub normals: Vec<f32>,
+    /// Flattened uv texture coordinates, one per position. Empty if the
+    /// source had none.
+    pub texcoords: Vec<f32>,
+    /// Indices in to `positions`, three per triangle.
+    pub indices: Vec<u32>,
+}
+
+impl IndexedMesh {
+    /// Copy only the vertices `indices` refer to, in the order first
+    /// referred to, with `indices` renumbered to match. The subset's own
+    /// `indices` are left empty.
+    pub fn subset(&self, indices: &[u32]) -> (IndexedMesh, Vec<u32>) {
+        let mut subset = IndexedMesh::default();
+        let mut renumbered = HashMap::new();
+        let new_indices = indices
+            .iter()
+            .map(|&i| {
+                let next = renumbered.len() as u32;
+                *renumbered.entry(i).or_insert_with(|| {
+                    let i = i as usize;
+                    subset
+                        .positions
+                        .extend_from_slice(&self.positions[i * 3..i * 3 + 3]);
+                    subset
+                        .normals
+                        .extend_from_slice(&self.normals[i * 3..i * 3 + 3]);
+                    if !self.texcoords.is_empty() {
+                        subset
+                            .texcoords
+                            .extend_from_slice(&self.texcoords[i * 2..i * 2 + 2]);
+                    }
+                    next
+                })
+            })
+            .collect();
+        (subset, new_indices)
+    }
+}
+
+/// Everything in a mesh file, keeping its object and group structure.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshSet {
+    pub objects: Vec<MeshObject>,
+}
+
+/// A named object within a mesh file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshObject {
+    pub name: String,
+    pub groups: Vec<MeshGroup>,
+}
+
+/// The faces, lines and points of an object sharing a group and material.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshGroup {
+    /// Name of the group, or `None` for the default group.
+    pub name: Option<String>,
+    /// Name of the material in the mesh's material library, if it set one.
+    pub material_name: Option<String>,
+    /// Vertices shared by every primitive, with triangle indices.
+    pub mesh: IndexedMesh,
+    /// Indices in to `mesh.positions`, two per line.
+    pub line_indices: Vec<u32>,
+    /// Indices in to `mesh.positions`, one per point.
+    pub point_indices: Vec<u32>,
+}
+
+/// Compute smooth vertex normals by summing the area weighted normals of
+/// every triangle touching each vertex. Triangles are taken to be wound
+/// counter-clockwise.
+pub fn compute_normals(positions: &[f32], indices: &[u32]) -> Vec<f32> {
+    let vert = |i: u32| {
+        let i = i as usize * 3;
+        Vector3::new(positions[i], positions[i + 1], positions[i + 2])
+    };
+    let mut sums = vec![Vector3::<f32>::zeros(); positions.len() / 3];
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (vert(tri[0]), vert(tri[1]), vert(tri[2]));
+        // The cross product's length is twice the triangle's area.
+        let face_normal = (b - a).cross(&(c - a));
+        for &i in tri {
+            sums[i as usize] += face_normal;
+        }
+    }
+    sums.iter()
+        .flat_map(|n| {
+            let n = n.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+            vec![n[0], n[1], n[2]]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normals_face_out_of_ccw_triangles() {
+        let positions = vec![0., 0., 0., 1., 0., 0., 0., 1., 0.];
+        assert_eq!(
+            compute_normals(&positions, &[0, 1, 2]),
+            vec![0., 0., 1., 0., 0., 1., 0., 0., 1.]
+        );
+        assert_eq!(
+            compute_normals(&positions, &[0, 2, 1]),
+            vec![0., 0., -1., 0., 0., -1., 0., 0., -1.]
+        );
+    }
+
+    #[test]
+    fn subset_keeps_only_referenced_vertices() {
+        let mesh = IndexedMesh {
+            positions: vec![0., 0., 0., 1., 1., 1., 2., 2., 2., 3., 3., 3.],
+            normals: vec![0., 0., 1., 0., 1., 0., 1., 0., 0., 0., 0., -1.],
+            texcoords: vec![],
+            indices: vec![0, 1, 2],
+        };
+        let (subset, indices) = mesh.subset(&[3, 1, 3]);
+        assert_eq!(indices, vec![0, 1, 0]);
+        assert_eq!(subset.positions, vec![3., 3., 3., 1., 1., 1.]);
+        assert_eq!(subset.normals, vec![0., 0., -1., 0., 1., 0.]);
+        assert!(subset.texcoords.is_empty());
+    }
+}
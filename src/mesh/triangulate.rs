@@ -0,0 +1,158 @@
+use na::{Vector2, Vector3};
+
+/// Split a simple polygon in to triangles wound the same way as the
+/// polygon. Convex polygons are fanned, concave ones are ear clipped.
+/// Returns triangles as indices in to `polygon`.
+pub fn triangulate(polygon: &[Vector3<f32>]) -> Vec<[usize; 3]> {
+    if polygon.len() < 3 {
+        return vec![];
+    }
+    let normal = match newell_normal(polygon).try_normalize(f32::EPSILON) {
+        Some(normal) => normal,
+        // No area to go on, so there's no wrong answer.
+        None => return fan(polygon.len()),
+    };
+    let flat = project(polygon, &normal);
+    if is_convex(&flat) {
+        fan(polygon.len())
+    } else {
+        ear_clip(&flat)
+    }
+}
+
+/// Normal of a possibly non-planar polygon, following its winding.
+/// Its length is twice the polygon's area.
+fn newell_normal(polygon: &[Vector3<f32>]) -> Vector3<f32> {
+    let mut normal = Vector3::zeros();
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        normal += Vector3::new(
+            (a[1] - b[1]) * (a[2] + b[2]),
+            (a[2] - b[2]) * (a[0] + b[0]),
+            (a[0] - b[0]) * (a[1] + b[1]),
+        );
+    }
+    normal
+}
+
+/// Flatten the polygon on to the plane facing `normal`, such that it winds
+/// counter-clockwise.
+fn project(polygon: &[Vector3<f32>], normal: &Vector3<f32>) -> Vec<Vector2<f32>> {
+    let helper = if normal[0].abs() < 0.9 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let u = normal.cross(&helper).normalize();
+    let v = normal.cross(&u);
+    polygon
+        .iter()
+        .map(|p| Vector2::new(p.dot(&u), p.dot(&v)))
+        .collect()
+}
+
+/// Twice the signed area of a triangle, positive when counter-clockwise.
+fn signed_area(a: &Vector2<f32>, b: &Vector2<f32>, c: &Vector2<f32>) -> f32 {
+    (b - a).perp(&(c - a))
+}
+
+fn is_convex(flat: &[Vector2<f32>]) -> bool {
+    let n = flat.len();
+    (0..n).all(|i| signed_area(&flat[i], &flat[(i + 1) % n], &flat[(i + 2) % n]) >= 0.)
+}
+
+fn fan(len: usize) -> Vec<[usize; 3]> {
+    (1..len - 1).map(|i| [0, i, i + 1]).collect()
+}
+
+fn ear_clip(flat: &[Vector2<f32>]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..flat.len()).collect();
+    let mut triangles = Vec::with_capacity(flat.len() - 2);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (prev, cur, next) = (
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            );
+            let (a, b, c) = (&flat[prev], &flat[cur], &flat[next]);
+            signed_area(a, b, c) > 0.
+                && !remaining
+                    .iter()
+                    .any(|&j| j != prev && j != cur && j != next && in_triangle(&flat[j], a, b, c))
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + n - 1) % n],
+                    remaining[i],
+                    remaining[(i + 1) % n],
+                ]);
+                remaining.remove(i);
+            }
+            // Only self-intersecting polygons run out of ears. Fan what's
+            // left rather than looping forever.
+            None => break,
+        }
+    }
+    triangles
+        .extend((1..remaining.len() - 1).map(|i| [remaining[0], remaining[i], remaining[i + 1]]));
+    triangles
+}
+
+fn in_triangle(p: &Vector2<f32>, a: &Vector2<f32>, b: &Vector2<f32>, c: &Vector2<f32>) -> bool {
+    signed_area(a, b, p) >= 0. && signed_area(b, c, p) >= 0. && signed_area(c, a, p) >= 0.
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area_along(polygon: &[Vector3<f32>], tris: &[[usize; 3]], normal: Vector3<f32>) -> f32 {
+        tris.iter()
+            .map(|&[a, b, c]| {
+                let area = (polygon[b] - polygon[a])
+                    .cross(&(polygon[c] - polygon[a]))
+                    .dot(&normal);
+                assert!(area > 0., "Triangle {:?} is wound backwards", [a, b, c]);
+                area * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn convex_polygons_are_fanned() {
+        let quad = vec![
+            Vector3::new(0., 0., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(1., 1., 0.),
+            Vector3::new(0., 1., 0.),
+        ];
+        let tris = triangulate(&quad);
+        assert_eq!(tris, vec![[0, 1, 2], [0, 2, 3]]);
+        assert!((area_along(&quad, &tris, Vector3::z()) - 1.).abs() < 1e-6);
+
+        // Clockwise when seen from +z, so the triangles must be too.
+        let reversed: Vec<_> = quad.iter().rev().copied().collect();
+        let tris = triangulate(&reversed);
+        assert!((area_along(&reversed, &tris, -Vector3::z()) - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn concave_polygons_are_ear_clipped() {
+        // An L shape in the xz plane, whose reflex corner would break a fan
+        // from vertex 0.
+        let l_shape = vec![
+            Vector3::new(2., 0., 1.),
+            Vector3::new(1., 0., 1.),
+            Vector3::new(1., 0., 2.),
+            Vector3::new(0., 0., 2.),
+            Vector3::new(0., 0., 0.),
+            Vector3::new(2., 0., 0.),
+        ];
+        let tris = triangulate(&l_shape);
+        assert_eq!(tris.len(), 4);
+        assert!((area_along(&l_shape, &tris, -Vector3::y()) - 3.).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use wavefront_obj::mtl::Color;
+
+use crate::world_object::Material;
+
+/// Parse wavefront mtl data into materials, keyed by name.
+pub fn into_materials(file_data: &str) -> Result<BTreeMap<String, Material>, String> {
+    let mtl_set = wavefront_obj::mtl::parse(file_data).map_err(|e| e.to_string())?;
+    Ok(mtl_set
+        .materials
+        .iter()
+        .map(|mtl| (mtl.name.clone(), into_material(mtl)))
+        .collect())
+}
+
+/// Map the `Kd`, `d`, `Ka`, `Ks` and `Ns` values of a material on to ours.
+fn into_material(mtl: &wavefront_obj::mtl::Material) -> Material {
+    let (r, g, b) = to_rgb(&mtl.color_diffuse);
+    Material {
+        color: (r, g, b, mtl.alpha as f32),
+        ambient: to_rgb(&mtl.color_ambient),
+        specular: to_rgb(&mtl.color_specular),
+        shininess: mtl.specular_coefficient as f32,
+        ..Default::default()
+    }
+}
+
+fn to_rgb(color: &Color) -> (f32, f32, f32) {
+    (color.r as f32, color.g as f32, color.b as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cottage_materials() {
+        let materials = into_materials(include_str!("../../www/assets/cottage_1.mtl")).unwrap();
+        assert_eq!(materials.len(), 2);
+        let wood = &materials["Material.001"];
+        assert!((wood.color.0 - 0.252943).abs() < 1e-6);
+        assert_eq!(wood.color.3, 1.);
+        assert_eq!(wood.ambient, (1., 1., 1.));
+        assert!((wood.shininess - 25.663024).abs() < 1e-4);
+        assert_eq!(materials["None"].specular, (0.8, 0.8, 0.8));
+    }
+}
@@ -1,10 +1,16 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
 use crate::js_bindings;
+use crate::mesh::triangulate::triangulate;
+use crate::mesh::{compute_normals, IndexedMesh, MeshGroup, MeshObject, MeshSet};
 
+use na::Vector3;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use wasm_bindgen_futures::JsFuture;
-use wavefront_obj::obj::{Object, Primitive};
+use wavefront_obj::obj::{ObjSet, Object, Primitive, Shape, VTNIndex};
 use web_sys::{Request, RequestInit, RequestMode, Response};
 
 // Parsing produces an ObjSet
@@ -15,30 +21,275 @@ use web_sys::{Request, RequestInit, RequestMode, Response};
 // Primitives are either Points, Lines, or Triangles
 // Points, Lines, and Triangles refer to vertices of the Obj
 
-pub fn into_vertex_vec(file_data: &str) -> Result<Vec<f32>, String> {
-    let mut vert_tups: Vec<(f64, f64, f64)> = Vec::new();
-    wavefront_obj::obj::parse(file_data)
-        .map(|obj_set| {
-            let obj = &obj_set.objects[0];
-            let geo = &obj.geometry[0];
-            for shape in &geo.shapes {
-                match shape.primitive {
-                    Primitive::Triangle(p1, p2, p3) => {
-                        vert_tups.push(get_vertex_pos(obj, p1.0));
-                        vert_tups.push(get_vertex_pos(obj, p2.0));
-                        vert_tups.push(get_vertex_pos(obj, p3.0));
+/// Parse the triangles of every object in wavefront obj data in to a
+/// single mesh of deduplicated vertices and triangle indices.
+/// Normals are read from the file, or computed if any are missing.
+/// Texture coordinates are kept only if every vertex has one.
+pub fn into_indexed_mesh(file_data: &str) -> Result<IndexedMesh, String> {
+    let obj_set = parse_obj(file_data)?;
+    let mut builder = IndexedMeshBuilder::default();
+    for (obj_idx, obj) in obj_set.objects.iter().enumerate() {
+        for geo in &obj.geometry {
+            builder.add_shapes(obj_idx, obj, geo.shapes.iter());
+        }
+    }
+    Ok(builder.build().mesh)
+}
+
+/// Parse wavefront obj data in to a mesh set with one `MeshObject` per
+/// object, each split in to one `MeshGroup` per group and material.
+/// Objects and groups are in the order they first appear.
+pub fn into_mesh_set(file_data: &str) -> Result<MeshSet, String> {
+    let obj_set = parse_obj(file_data)?;
+    let objects = obj_set
+        .objects
+        .iter()
+        .enumerate()
+        .map(|(obj_idx, obj)| {
+            let mut builders: Vec<(GroupKey, IndexedMeshBuilder)> = vec![];
+            for geo in &obj.geometry {
+                for shape in &geo.shapes {
+                    let key = (group_name(shape), geo.material_name.clone());
+                    let pos = match builders.iter().position(|(k, _)| *k == key) {
+                        Some(pos) => pos,
+                        None => {
+                            builders.push((key, Default::default()));
+                            builders.len() - 1
+                        }
+                    };
+                    builders[pos]
+                        .1
+                        .add_shapes(obj_idx, obj, std::iter::once(shape));
+                }
+            }
+            MeshObject {
+                name: obj.name.clone(),
+                groups: builders
+                    .into_iter()
+                    .map(|((name, material_name), builder)| MeshGroup {
+                        name,
+                        material_name,
+                        ..builder.build()
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+    Ok(MeshSet { objects })
+}
+
+/// Group and material names.
+type GroupKey = (Option<String>, Option<String>);
+
+/// Shapes in several groups at once are put in a group named after all of
+/// them.
+fn group_name(shape: &Shape) -> Option<String> {
+    if shape.groups.is_empty() {
+        None
+    } else {
+        Some(shape.groups.join(" "))
+    }
+}
+
+fn parse_obj(file_data: &str) -> Result<ObjSet, String> {
+    wavefront_obj::obj::parse(split_polygons(file_data)).map_err(|e| e.to_string())
+}
+
+/// Rewrite faces with more than three corners as triangles, and polylines
+/// as separate line segments. The parser would otherwise fan both, which
+/// breaks concave faces and turns polylines in to triangles. Negative
+/// indices in rewritten lines are made absolute, as the parser mishandles
+/// them.
+fn split_polygons(file_data: &str) -> Cow<'_, str> {
+    if !file_data.lines().any(needs_rewrite) {
+        return Cow::Borrowed(file_data);
+    }
+    let mut positions: Vec<Vector3<f32>> = vec![];
+    // Number of v, vt and vn entries so far.
+    let mut counts = [0; 3];
+    let mut out = String::with_capacity(file_data.len());
+    for line in file_data.lines() {
+        let mut tokens = line.split_whitespace();
+        let keyword = tokens.next();
+        match keyword {
+            Some("v") => {
+                let mut coords = tokens.by_ref().map(|t| t.parse::<f32>().unwrap_or(0.));
+                let mut next = || coords.next().unwrap_or(0.);
+                positions.push(Vector3::new(next(), next(), next()));
+                counts[0] += 1;
+            }
+            Some("vt") => counts[1] += 1,
+            Some("vn") => counts[2] += 1,
+            _ => {}
+        }
+        let corners: Option<Vec<String>> = if needs_rewrite(line) {
+            tokens.map(|c| absolute_corner(c, &counts)).collect()
+        } else {
+            None
+        };
+        // Lines with bad indices are left for the parser to report.
+        match (keyword, corners) {
+            (Some("f"), Some(corners)) => {
+                let polygon: Option<Vec<_>> = corners
+                    .iter()
+                    .map(|c| corner_position(c, &positions))
+                    .collect();
+                if let Some(polygon) = polygon {
+                    for [a, b, c] in triangulate(&polygon) {
+                        out.push_str(&format!("f {} {} {}\n", corners[a], corners[b], corners[c]));
                     }
-                    Primitive::Line(p1, p2) => {
-                        js_bindings::error(&format!("Line found! {:?} {:?}", p1, p2));
+                    continue;
+                }
+            }
+            (Some("l"), Some(corners)) => {
+                for segment in corners.windows(2) {
+                    out.push_str(&format!("l {} {}\n", segment[0], segment[1]));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    Cow::Owned(out)
+}
+
+fn needs_rewrite(line: &str) -> bool {
+    let mut tokens = line.split_whitespace();
+    let max_corners = match tokens.next() {
+        Some("f") => 3,
+        Some("l") => 2,
+        _ => return false,
+    };
+    let corners: Vec<&str> = tokens.collect();
+    corners.len() > max_corners || corners.iter().any(|c| c.contains('-'))
+}
+
+/// Make the indices of a `v/vt/vn` corner absolute, given how many of each
+/// kind of vertex came before it.
+fn absolute_corner(corner: &str, counts: &[usize; 3]) -> Option<String> {
+    let parts: Option<Vec<String>> = corner
+        .split('/')
+        .zip(counts.iter())
+        .map(|(idx, &count)| {
+            if idx.is_empty() {
+                return Some(String::new());
+            }
+            let idx: isize = idx.parse().ok()?;
+            if idx < 0 {
+                Some((count as isize + idx + 1).to_string())
+            } else {
+                Some(idx.to_string())
+            }
+        })
+        .collect();
+    parts.map(|parts| parts.join("/"))
+}
+
+/// Look up the position of an absolute `v/vt/vn` corner.
+fn corner_position(corner: &str, positions: &[Vector3<f32>]) -> Option<Vector3<f32>> {
+    let v_idx: usize = corner.split('/').next()?.parse().ok()?;
+    positions.get(v_idx.checked_sub(1)?).copied()
+}
+
+/// Return the url of the material library obj data refers to, taken to be
+/// relative to the obj file's own url.
+pub fn material_library_url(obj_url: &str, file_data: &str) -> Option<String> {
+    let lib_name = file_data
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("mtllib "))?
+        .trim();
+    match obj_url.rfind('/') {
+        Some(dir_end) => Some(format!("{}{}", &obj_url[..=dir_end], lib_name)),
+        None => Some(lib_name.to_owned()),
+    }
+}
+
+/// Gathers deduplicated vertices from the shapes of one or more objects.
+#[derive(Default)]
+struct IndexedMeshBuilder {
+    group: MeshGroup,
+    // Vertices sharing a position but not a uv or normal must stay separate.
+    seen: BTreeMap<(usize, VTNIndex), u32>,
+    missing_normals: bool,
+    missing_texcoords: bool,
+}
+
+impl IndexedMeshBuilder {
+    fn add_shapes<'a>(
+        &mut self,
+        obj_idx: usize,
+        obj: &Object,
+        shapes: impl Iterator<Item = &'a Shape>,
+    ) {
+        for shape in shapes {
+            match shape.primitive {
+                Primitive::Triangle(p1, p2, p3) => {
+                    for p in &[p1, p2, p3] {
+                        let idx = self.index_of(obj_idx, obj, *p);
+                        self.group.mesh.indices.push(idx);
                     }
-                    Primitive::Point(p) => {
-                        js_bindings::error(&format!("Point found! {:?}", p));
+                }
+                Primitive::Line(p1, p2) => {
+                    for p in &[p1, p2] {
+                        let idx = self.index_of(obj_idx, obj, *p);
+                        self.group.line_indices.push(idx);
                     }
                 }
+                Primitive::Point(p) => {
+                    let idx = self.index_of(obj_idx, obj, p);
+                    self.group.point_indices.push(idx);
+                }
             }
-            concat_vert_tups(&vert_tups)
-        })
-        .map_err(|e| e.to_string())
+        }
+    }
+
+    fn index_of(&mut self, obj_idx: usize, obj: &Object, vtn: VTNIndex) -> u32 {
+        if let Some(&idx) = self.seen.get(&(obj_idx, vtn)) {
+            return idx;
+        }
+        let mesh = &mut self.group.mesh;
+        let (v_id, t_id, n_id) = vtn;
+        let (x, y, z) = get_vertex_pos(obj, v_id);
+        mesh.positions
+            .extend_from_slice(&[x as f32, y as f32, z as f32]);
+        match t_id {
+            Some(t_id) => {
+                let t = obj.tex_vertices[t_id];
+                mesh.texcoords.extend_from_slice(&[t.u as f32, t.v as f32]);
+            }
+            None => {
+                self.missing_texcoords = true;
+                mesh.texcoords.extend_from_slice(&[0., 0.]);
+            }
+        }
+        match n_id {
+            Some(n_id) => {
+                let n = obj.normals[n_id];
+                mesh.normals
+                    .extend_from_slice(&[n.x as f32, n.y as f32, n.z as f32]);
+            }
+            None => {
+                self.missing_normals = true;
+                mesh.normals.extend_from_slice(&[0., 0., 0.]);
+            }
+        }
+        let idx = (mesh.positions.len() / 3 - 1) as u32;
+        self.seen.insert((obj_idx, vtn), idx);
+        idx
+    }
+
+    fn build(mut self) -> MeshGroup {
+        let mesh = &mut self.group.mesh;
+        if self.missing_normals {
+            mesh.normals = compute_normals(&mesh.positions, &mesh.indices);
+        }
+        if self.missing_texcoords {
+            mesh.texcoords.clear();
+        }
+        self.group
+    }
 }
 
 pub fn mesh_string() -> String {
@@ -53,33 +304,20 @@ fn get_vertex_pos(obj: &Object, v_id: usize) -> (f64, f64, f64) {
     (vert.x, vert.y, vert.z)
 }
 
-fn concat_vert_tups(vert_tups: &[(f64, f64, f64)]) -> Vec<f32> {
-    let mut out_vec: Vec<f32> = Vec::new();
-    vert_tups
-        .iter()
-        .fold(&mut out_vec, |acc: &mut Vec<_>, &(x, y, z)| {
-            acc.push(x as f32);
-            acc.push(y as f32);
-            acc.push(z as f32);
-            acc
-        });
-    out_vec
-}
+/// Filled with a fetched file's contents, or the reason it couldn't be fetched.
+pub type MeshDataCell = std::sync::Arc<std::sync::Mutex<Option<Result<String, String>>>>;
 
-pub fn get_mesh_data_from_url(url: String) -> std::sync::Arc<std::sync::Mutex<Option<String>>> {
+/// Start fetching a text file in the background.
+pub fn get_mesh_data_from_url(url: String) -> MeshDataCell {
     let out = std::sync::Arc::new(std::sync::Mutex::new(None));
     let out_clone = out.clone();
     wasm_bindgen_futures::spawn_local(async move {
-        let result = async_get_mesh_data_from_url(&url).await;
-        match (out_clone.try_lock(), result) {
-            (Ok(mut lock), Ok(r)) => {
-                *lock = Some(r);
-            }
-            (Ok(mut lock), Err(e)) => {
-                js_bindings::error(&e);
-                *lock = None;
-            }
-            (Err(e), _) => js_bindings::error(&format!(
+        let result = async_get_mesh_data_from_url(&url)
+            .await
+            .map_err(|e| format!("Unable to load {}: {}", url, e));
+        match out_clone.try_lock() {
+            Ok(mut lock) => *lock = Some(result),
+            Err(e) => js_bindings::error(&format!(
                 "Unable to acquire lock for mesh data output arc: {}",
                 e.to_string()
             )),
@@ -101,16 +339,171 @@ pub async fn async_get_mesh_data_from_url(url: &str) -> Result<String, String> {
     let resp_value: JsValue = JsFuture::from(window.fetch_with_request(&request))
         .await
         .map_err(|x| x.as_string().unwrap_or("Couldn't convert to string".into()))?;
-    let resp_text: JsValue = JsFuture::from(
-        resp_value
-            .dyn_into::<Response>()
-            .expect("Not an instance of Response")
-            .text()
-            .unwrap(),
-    )
-    .await
-    .map_err(|e| e.as_string().unwrap_or("Couldn't convert error".into()))?;
+    let resp = resp_value
+        .dyn_into::<Response>()
+        .expect("Not an instance of Response");
+    if !resp.ok() {
+        return Err(format!("HTTP {} {}", resp.status(), resp.status_text()));
+    }
+    let resp_text: JsValue = JsFuture::from(resp.text().unwrap())
+        .await
+        .map_err(|e| e.as_string().unwrap_or("Couldn't convert error".into()))?;
     Ok(resp_text
         .as_string()
         .expect("Could not convert response text to String"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "o Quad
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 1.0 0.0
+v 0.0 1.0 0.0
+f 1 2 3
+f 1 3 4
+";
+
+    #[test]
+    fn indexed_mesh_shares_vertices() {
+        let mesh = into_indexed_mesh(QUAD).unwrap();
+        assert_eq!(mesh.positions.len(), 4 * 3);
+        assert_eq!(mesh.indices.len(), 6);
+        assert_eq!(mesh.indices.iter().max(), Some(&3));
+        // No normals in the file, so they're computed from the faces.
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+        for n in mesh.normals.chunks_exact(3) {
+            assert_eq!(n, &[0., 0., 1.]);
+        }
+    }
+
+    #[test]
+    fn indexed_mesh_splits_on_normals() {
+        let data = "o Wedge
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 0.0 1.0 0.0
+v 0.0 0.0 1.0
+vn 0.0 0.0 1.0
+vn 0.0 1.0 0.0
+f 1//1 2//1 3//1
+f 1//2 4//2 2//2
+";
+        let mesh = into_indexed_mesh(data).unwrap();
+        // Vertices 1 and 2 are used with two different normals.
+        assert_eq!(mesh.positions.len(), 6 * 3);
+        assert_eq!(mesh.normals.len(), 6 * 3);
+    }
+
+    #[test]
+    fn indexed_mesh_reads_texcoords() {
+        let data = "o Quad
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 1.0 0.0
+v 0.0 1.0 0.0
+vt 0.0 0.0
+vt 1.0 0.0
+vt 1.0 1.0
+vt 0.0 1.0
+vt 0.5 0.5
+f 1/1 2/2 3/3
+f 1/5 3/3 4/4
+";
+        let mesh = into_indexed_mesh(data).unwrap();
+        // Vertex 1 is used with two different uvs.
+        assert_eq!(mesh.positions.len(), 5 * 3);
+        assert_eq!(mesh.texcoords.len(), 5 * 2);
+        for (pos, uv) in mesh
+            .positions
+            .chunks_exact(3)
+            .zip(mesh.texcoords.chunks_exact(2))
+        {
+            if pos[0] == 1.0 && pos[1] == 1.0 {
+                assert_eq!(uv, &[1., 1.]);
+            }
+        }
+        // No uvs in the file, so none are kept.
+        assert!(into_indexed_mesh(QUAD).unwrap().texcoords.is_empty());
+    }
+
+    #[test]
+    fn mesh_set_keeps_objects_and_groups() {
+        let data = "mtllib quad.mtl
+o Quad
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 1.0 0.0
+v 0.0 1.0 0.0
+g Front
+usemtl Red
+f 1 2 3
+usemtl Blue
+f 1 3 4
+g Outline
+l 1 2
+o Triangle
+v 0.0 0.0 1.0
+v 1.0 0.0 1.0
+v 1.0 1.0 1.0
+usemtl Red
+f 5 6 7
+l 5
+";
+        let set = into_mesh_set(data).unwrap();
+        let names: Vec<_> = set.objects.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["Quad", "Triangle"]);
+
+        let quad = &set.objects[0].groups;
+        let groups: Vec<_> = quad
+            .iter()
+            .map(|g| (g.name.as_deref(), g.material_name.as_deref()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (Some("Front"), Some("Red")),
+                (Some("Front"), Some("Blue")),
+                (Some("Outline"), Some("Blue")),
+            ]
+        );
+        assert_eq!(quad[0].mesh.indices.len(), 3);
+        assert_eq!(quad[2].line_indices, vec![0, 1]);
+        assert_eq!(quad[2].mesh.positions.len(), 2 * 3);
+
+        let triangle = &set.objects[1].groups[0];
+        assert_eq!(triangle.mesh.indices.len(), 3);
+        assert_eq!(triangle.point_indices.len(), 1);
+
+        // Flattening keeps the triangles of every object.
+        assert_eq!(into_indexed_mesh(data).unwrap().indices.len(), 9);
+        assert_eq!(
+            material_library_url("assets/quad.obj", data),
+            Some("assets/quad.mtl".to_owned())
+        );
+        assert_eq!(material_library_url("assets/quad.obj", QUAD), None);
+    }
+
+    #[test]
+    fn polygons_are_triangulated() {
+        let data = "o Quad
+v 0.0 0.0 0.0
+v 1.0 0.0 0.0
+v 1.0 1.0 0.0
+v 0.0 1.0 0.0
+f 1 2 3 4
+l 1 2 3 -1
+";
+        let set = into_mesh_set(data).unwrap();
+        let group = &set.objects[0].groups[0];
+        assert_eq!(group.mesh.indices.len(), 6);
+        // Computed normals only face +z if the winding was kept.
+        for n in group.mesh.normals.chunks_exact(3) {
+            assert_eq!(n, &[0., 0., 1.]);
+        }
+        // The polyline is three segments, not a triangle.
+        assert_eq!(group.line_indices.len(), 6);
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::world_object::WorldObjectId;
+
+/// World object ids by name and by tag, so lookups don't scan every
+/// object.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectIndex {
+    by_name: BTreeMap<String, BTreeSet<WorldObjectId>>,
+    by_tag: BTreeMap<String, BTreeSet<WorldObjectId>>,
+}
+
+fn insert(map: &mut BTreeMap<String, BTreeSet<WorldObjectId>>, key: &str, id: WorldObjectId) {
+    map.entry(key.to_owned()).or_default().insert(id);
+}
+
+fn remove(map: &mut BTreeMap<String, BTreeSet<WorldObjectId>>, key: &str, id: WorldObjectId) {
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+impl ObjectIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index an object. Unnamed objects are only found through tags.
+    pub fn insert<'a>(
+        &mut self,
+        id: WorldObjectId,
+        name: &str,
+        tags: impl IntoIterator<Item = &'a String>,
+    ) {
+        if !name.is_empty() {
+            insert(&mut self.by_name, name, id);
+        }
+        for tag in tags {
+            insert(&mut self.by_tag, tag, id);
+        }
+    }
+
+    pub fn remove<'a>(
+        &mut self,
+        id: WorldObjectId,
+        name: &str,
+        tags: impl IntoIterator<Item = &'a String>,
+    ) {
+        remove(&mut self.by_name, name, id);
+        for tag in tags {
+            remove(&mut self.by_tag, tag, id);
+        }
+    }
+
+    pub fn rename(&mut self, id: WorldObjectId, old: &str, new: &str) {
+        remove(&mut self.by_name, old, id);
+        if !new.is_empty() {
+            insert(&mut self.by_name, new, id);
+        }
+    }
+
+    pub fn add_tag(&mut self, id: WorldObjectId, tag: &str) {
+        insert(&mut self.by_tag, tag, id);
+    }
+
+    pub fn remove_tag(&mut self, id: WorldObjectId, tag: &str) {
+        remove(&mut self.by_tag, tag, id);
+    }
+
+    /// Return every object with the given name, in id order.
+    pub fn with_name(&self, name: &str) -> Vec<WorldObjectId> {
+        self.by_name
+            .get(name)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Return every object having all of the given tags, in id order.
+    pub fn with_tags(&self, tags: &[&str]) -> Vec<WorldObjectId> {
+        let mut sets = tags.iter().map(|tag| self.by_tag.get(*tag));
+        let first = match sets.next() {
+            Some(Some(first)) => first,
+            _ => return vec![],
+        };
+        let rest: Option<Vec<_>> = sets.collect();
+        match rest {
+            Some(rest) => first
+                .iter()
+                .filter(|id| rest.iter().all(|set| set.contains(id)))
+                .copied()
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_object::WorldObject3DInit;
+    use crate::world_state::WorldState;
+
+    #[test]
+    fn finds_by_name_and_tags() {
+        let tags = |t: &[&str]| -> Vec<String> { t.iter().map(|&t| t.to_owned()).collect() };
+        let mut index = ObjectIndex::new();
+        index.insert(WorldObjectId(0), "door", &tags(&["wood", "open"]));
+        index.insert(WorldObjectId(1), "door", &tags(&["wood"]));
+        index.insert(WorldObjectId(2), "", &tags(&["open"]));
+
+        assert_eq!(
+            index.with_name("door"),
+            vec![WorldObjectId(0), WorldObjectId(1)]
+        );
+        assert_eq!(index.with_name(""), vec![]);
+        assert_eq!(index.with_tags(&["wood", "open"]), vec![WorldObjectId(0)]);
+        assert_eq!(index.with_tags(&["wood", "missing"]), vec![]);
+        assert_eq!(index.with_tags(&[]), vec![]);
+
+        index.rename(WorldObjectId(1), "door", "gate");
+        index.remove_tag(WorldObjectId(0), "open");
+        assert_eq!(index.with_name("door"), vec![WorldObjectId(0)]);
+        assert_eq!(index.with_tags(&["open"]), vec![WorldObjectId(2)]);
+
+        index.remove(WorldObjectId(0), "door", &tags(&["wood"]));
+        assert_eq!(index.with_name("door"), vec![]);
+        assert_eq!(index.with_tags(&["wood"]), vec![WorldObjectId(1)]);
+    }
+
+    #[test]
+    fn path_search_tries_every_root() {
+        let mut state = WorldState::new();
+        let mut add = |name: &str, parent| {
+            state.add_world_obj(
+                WorldObject3DInit {
+                    name: name.to_owned(),
+                    parent_id: parent,
+                    ..Default::default()
+                }
+                .init(),
+            )
+        };
+        let first = add("cottage", None);
+        add("door", Some(first));
+        let second = add("cottage", None);
+        let roof = add("roof", Some(second));
+        add("chimney", Some(roof));
+        let chimney = add("chimney", Some(roof));
+        let smoke = add("smoke", Some(chimney));
+
+        assert_eq!(state.find_by_path("cottage"), Some(first));
+        assert_eq!(state.find_by_path("cottage/roof"), Some(roof));
+        assert_eq!(
+            state.find_by_path("/cottage/roof/chimney/smoke"),
+            Some(smoke)
+        );
+        assert_eq!(state.find_by_path("cottage/cellar"), None);
+    }
+}
@@ -0,0 +1,75 @@
+use std::rc::Rc;
+
+use crate::mesh::wavefront_obj::into_indexed_mesh;
+use crate::mesh::IndexedMesh;
+use crate::rendering::ProgramData;
+use crate::scene::MeshRef;
+use crate::steppables::ObjectScript;
+use crate::transform::Transform;
+use crate::util::Rfc;
+use crate::world_object::Material;
+
+/// Makes a fresh script for each instance of a prefab.
+pub type ScriptFactory = Rc<dyn Fn() -> Rfc<dyn ObjectScript>>;
+
+/// A template for a tree of world objects, registered by name through
+/// `WorldState::register_prefab`.
+#[derive(Clone)]
+pub struct Prefab {
+    /// Used when instantiated without a transform override.
+    pub tf: Transform,
+    pub mesh: Option<PrefabMesh>,
+    pub scripts: Vec<ScriptFactory>,
+    pub children: Vec<PrefabChild>,
+}
+
+impl Default for Prefab {
+    fn default() -> Self {
+        Self {
+            tf: Transform::identity(),
+            mesh: None,
+            scripts: vec![],
+            children: vec![],
+        }
+    }
+}
+
+/// A mesh shared between instances, which each upload their own copy
+/// unless drawn instanced.
+#[derive(Clone)]
+pub struct PrefabMesh {
+    pub mesh: Rc<IndexedMesh>,
+    pub program_data: ProgramData,
+    pub material: Material,
+    /// Where `mesh` came from, so instances can be saved in scenes.
+    pub mesh_ref: Option<MeshRef>,
+    /// If set, every instance is drawn in one instanced draw with this
+    /// program, sharing one copy of the mesh. Only the material's colour
+    /// is used.
+    pub instanced_program_data: Option<ProgramData>,
+}
+
+impl PrefabMesh {
+    /// Build from the text of an OBJ file, flattened in to one mesh.
+    pub fn from_obj(
+        obj_data: &str,
+        program_data: ProgramData,
+        material: Material,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            mesh: Rc::new(into_indexed_mesh(obj_data)?),
+            program_data,
+            material,
+            mesh_ref: None,
+            instanced_program_data: None,
+        })
+    }
+}
+
+/// Another registered prefab instantiated as a child.
+#[derive(Debug, Clone)]
+pub struct PrefabChild {
+    pub prefab: String,
+    /// Overrides the child prefab's own transform.
+    pub tf: Option<Transform>,
+}
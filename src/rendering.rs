@@ -1,16 +1,23 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::rc::Rc;
 
 use na::Vector3;
+use slab::Slab;
 use web_sys::{
     WebGl2RenderingContext as Gl, WebGlBuffer as GlBuffer, WebGlProgram,
     WebGlUniformLocation as GlULoc, WebGlVertexArrayObject as GlVao,
 };
 
+use crate::camera::Camera;
 use crate::js_bindings;
+use crate::lighting::Lighting;
+use crate::maths_utils::Angle;
+use crate::texture::{Texture, DIFFUSE_TEXTURE_UNIFORM};
 use crate::transform::Transform;
+use crate::util::Rfc;
 use crate::{build_setter, build_setter_defaulted};
 
 #[derive(Debug, Clone, Copy)]
@@ -23,6 +30,7 @@ enum DrawnStatus {
 pub struct RenderableQueues {
     forward_queue: Vec<(DrawnStatus, Rc<RenderItem>)>,
     reverse_queue: Vec<(DrawnStatus, Rc<RenderItem>)>,
+    instanced_queue: Vec<(DrawnStatus, Rc<InstancedRenderItem>)>,
 }
 
 impl RenderableQueues {
@@ -30,9 +38,18 @@ impl RenderableQueues {
         Self {
             forward_queue: Vec::new(),
             reverse_queue: Vec::new(),
+            instanced_queue: Vec::new(),
         }
     }
 
+    pub fn push_instanced_queue(&mut self, item: Rc<InstancedRenderItem>) {
+        self.instanced_queue.push((DrawnStatus::NeedsDraw, item));
+    }
+
+    pub fn get_instanced_queue_len(&self) -> usize {
+        self.instanced_queue.len()
+    }
+
     pub fn push_forward_queue(&mut self, item: Rc<RenderItem>) {
         self.forward_queue.push((DrawnStatus::NeedsDraw, item));
     }
@@ -48,6 +65,14 @@ impl RenderableQueues {
     pub fn get_reverse_queue_len(&self) -> usize {
         self.reverse_queue.len()
     }
+
+    /// Stop drawing an item. Returns false if it was in neither queue.
+    pub fn remove_item(&mut self, item: &Rc<RenderItem>) -> bool {
+        let before = self.forward_queue.len() + self.reverse_queue.len();
+        self.forward_queue.retain(|(_, i)| !Rc::ptr_eq(i, item));
+        self.reverse_queue.retain(|(_, i)| !Rc::ptr_eq(i, item));
+        before != self.forward_queue.len() + self.reverse_queue.len()
+    }
 }
 
 /// Type alias to indicate a value is meant to be a location.
@@ -62,11 +87,26 @@ pub enum GlLocationType {
 
 #[derive(Debug, Clone)]
 pub struct ProgramData {
+    program: WebGlProgram,
     uniforms: BTreeMap<String, GlULoc>,
     attributes: BTreeMap<String, GlAttrLoc>,
     vaos: BTreeMap<String, GlVao>,
 }
 
+impl ProgramData {
+    pub fn program(&self) -> &WebGlProgram {
+        &self.program
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes.contains_key(name)
+    }
+
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains_key(name)
+    }
+}
+
 pub trait Bufferable: std::fmt::Debug {
     fn as_buffer(&self) -> js_sys::ArrayBuffer;
     fn len(&self) -> u32;
@@ -92,6 +132,26 @@ impl Bufferable for Vec<u8> {
     }
 }
 
+impl Bufferable for Vec<u16> {
+    fn as_buffer(&self) -> js_sys::ArrayBuffer {
+        js_sys::Uint16Array::from(self.as_ref()).buffer()
+    }
+
+    fn len(&self) -> u32 {
+        self.len() as u32
+    }
+}
+
+impl Bufferable for Vec<u32> {
+    fn as_buffer(&self) -> js_sys::ArrayBuffer {
+        js_sys::Uint32Array::from(self.as_ref()).buffer()
+    }
+
+    fn len(&self) -> u32 {
+        self.len() as u32
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BufferSettings {
     pub dim: u8,
@@ -138,10 +198,32 @@ impl BufferDataBind {
     }
 }
 
+/// Vertex indices bound to the `ELEMENT_ARRAY_BUFFER`.
+#[derive(Debug, Clone)]
+pub struct IndexBufferBind {
+    webgl_buffer: GlBuffer,
+    data: js_sys::ArrayBuffer,
+    count: u32,
+    /// Either `UNSIGNED_SHORT` or `UNSIGNED_INT`.
+    index_type: u32,
+}
+
+impl IndexBufferBind {
+    /// Return the number of indices.
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn index_type(&self) -> u32 {
+        self.index_type
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct BufferInfo {
     ctx: Gl,
     buffers: BTreeMap<String, BufferDataBind>,
+    indices: Option<IndexBufferBind>,
 }
 
 impl BufferInfo {
@@ -149,6 +231,7 @@ impl BufferInfo {
         BufferInfo {
             ctx,
             buffers: BTreeMap::new(),
+            indices: None,
         }
     }
 
@@ -175,9 +258,32 @@ impl BufferInfo {
         Self {
             ctx: self.ctx,
             buffers: self.buffers,
+            indices: self.indices,
         }
     }
 
+    /// Set the vertex indices to draw with, stored as u16s when they fit.
+    pub fn set_indices(mut self, indices: Vec<u32>) -> Self {
+        let webgl_buffer = self
+            .ctx
+            .create_buffer()
+            .expect("Unable to create index buffer");
+        let count = indices.len() as u32;
+        let (data, index_type) = if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            let short_indices: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+            (short_indices.as_buffer(), Gl::UNSIGNED_SHORT)
+        } else {
+            (indices.as_buffer(), Gl::UNSIGNED_INT)
+        };
+        self.indices = Some(IndexBufferBind {
+            webgl_buffer,
+            data,
+            count,
+            index_type,
+        });
+        self
+    }
+
     /// name order does not matter.
     pub fn from_data(
         ctx: Gl,
@@ -198,7 +304,11 @@ impl BufferInfo {
                 },
             );
         }
-        BufferInfo { ctx, buffers }
+        BufferInfo {
+            ctx,
+            buffers,
+            indices: None,
+        }
     }
 
     /// Write data to the named buffers stored in this BufferInfo.
@@ -219,6 +329,64 @@ impl BufferInfo {
     pub fn get_buffers(&self) -> &BTreeMap<String, BufferDataBind> {
         &self.buffers
     }
+
+    pub fn get_indices(&self) -> Option<&IndexBufferBind> {
+        self.indices.as_ref()
+    }
+
+    /// Free every buffer on the GPU. The buffers must not be drawn from
+    /// afterwards.
+    pub fn delete_buffers(&self) {
+        for data_bind in self.buffers.values() {
+            self.ctx.delete_buffer(Some(&data_bind.webgl_buffer));
+        }
+        if let Some(indices) = &self.indices {
+            self.ctx.delete_buffer(Some(&indices.webgl_buffer));
+        }
+    }
+
+    /// Upload every buffer and point the matching program attributes at
+    /// them. Expects the target VAO to already be bound.
+    pub fn write_attribute_data(&self, ctx: &Gl, program_data: &ProgramData) -> Result<(), String> {
+        for (buffer_name, bind) in self.buffers.iter() {
+            let attr_loc = program_data
+                .attributes
+                .get(buffer_name)
+                .ok_or(format!("Buffer attribute {} not found", buffer_name))?;
+            ctx.bind_buffer(Gl::ARRAY_BUFFER, Some(&bind.webgl_buffer));
+            ctx.buffer_data_with_opt_array_buffer(
+                Gl::ARRAY_BUFFER,
+                Some(&bind.data),
+                Gl::STATIC_DRAW,
+            );
+            Self::setup_vertex_attrib(ctx, attr_loc, &bind.settings);
+        }
+        // The element array binding is stored on the bound VAO.
+        if let Some(indices) = self.get_indices() {
+            ctx.bind_buffer(Gl::ELEMENT_ARRAY_BUFFER, Some(&indices.webgl_buffer));
+            ctx.buffer_data_with_opt_array_buffer(
+                Gl::ELEMENT_ARRAY_BUFFER,
+                Some(&indices.data),
+                Gl::STATIC_DRAW,
+            );
+        }
+        Ok(())
+    }
+
+    /// Bind the vertex attribute to the passed in context.
+    /// The bind settings determine how the given attribute will be bound.
+    fn setup_vertex_attrib(ctx: &Gl, attr_loc: &GlAttrLoc, bind_settings: &BufferSettings) {
+        let GlAttrLoc(attr_loc) = *attr_loc;
+        ctx.enable_vertex_attrib_array(attr_loc as u32);
+        ctx.vertex_attrib_pointer_with_i32(
+            attr_loc as u32,
+            bind_settings.dim as i32,
+            bind_settings.data_type,
+            bind_settings.normalize,
+            bind_settings.stride,
+            bind_settings.offset,
+        );
+    }
 }
 
 #[derive(Debug, Default)]
@@ -226,6 +394,35 @@ pub struct Assigned;
 #[derive(Debug, Default)]
 pub struct ToAssign;
 
+/// Material values lit programs read as uniforms. The diffuse colour
+/// travels with the vertices instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialUniforms {
+    /// Multiplies the world's ambient light.
+    pub ambient: (f32, f32, f32),
+    pub specular: (f32, f32, f32),
+    pub shininess: f32,
+    /// Items with an opacity below 1 are blended, after opaque items.
+    pub opacity: f32,
+}
+
+impl Default for MaterialUniforms {
+    fn default() -> Self {
+        Self {
+            ambient: (1., 1., 1.),
+            specular: (0., 0., 0.),
+            shininess: 0.,
+            opacity: 1.,
+        }
+    }
+}
+
+impl MaterialUniforms {
+    pub fn is_translucent(&self) -> bool {
+        self.opacity < 1.
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderItem {
     program_data: ProgramData,
@@ -236,6 +433,9 @@ pub struct RenderItem {
     face_normal_vao: Option<GlVao>,
     draw_type: u32,
     always_redraw: bool,
+    /// Sampler uniform names and the textures bound to them.
+    textures: Vec<(String, Rc<Texture>)>,
+    material: MaterialUniforms,
 }
 
 impl RenderItem {
@@ -252,6 +452,8 @@ pub struct RenderItemBuilder {
     vao: Option<GlVao>,
     face_normal_vao: Option<GlVao>,
     draw_type: Option<u32>,
+    textures: Vec<(String, Rc<Texture>)>,
+    material: MaterialUniforms,
 }
 
 impl RenderItemBuilder {
@@ -264,6 +466,8 @@ impl RenderItemBuilder {
             vao: None,
             face_normal_vao: None,
             draw_type: Some(Gl::TRIANGLES),
+            textures: Vec::new(),
+            material: MaterialUniforms::default(),
         }
     }
 
@@ -277,6 +481,8 @@ impl RenderItemBuilder {
             face_normal_vao: self.face_normal_vao,
             draw_type: self.draw_type?,
             always_redraw: false,
+            textures: self.textures,
+            material: self.material,
         })
     }
 
@@ -287,6 +493,8 @@ impl RenderItemBuilder {
     build_setter!(draw_type, u32);
     build_setter!(buffer_info, BufferInfo);
     build_setter!(program_data, ProgramData);
+    build_setter_defaulted!(textures, Vec<(String, Rc<Texture>)>);
+    build_setter_defaulted!(material, MaterialUniforms);
 }
 
 pub struct RenderLineBuilder<'a, RendererT: ?Sized> {
@@ -340,6 +548,8 @@ impl<'a, RendererT: Renderer + ?Sized> RenderLineBuilder<'a, RendererT> {
             face_normal_vao: None,
             draw_type: Gl::LINES,
             always_redraw: false,
+            textures: Vec::new(),
+            material: MaterialUniforms::default(),
         })
     }
 
@@ -403,49 +613,209 @@ impl RenderItem {
         &self.buffer_info
     }
 
-    /// Bind the vertex attribute to the passed in context.
-    /// The bind settings determine how the given attribute will be bound.
-    fn setup_vertex_attrib(ctx: &Gl, attr_loc: &GlAttrLoc, bind_settings: &BufferSettings) {
-        let GlAttrLoc(attr_loc) = *attr_loc;
-        ctx.enable_vertex_attrib_array(attr_loc as u32);
-        ctx.vertex_attrib_pointer_with_i32(
-            attr_loc as u32,
-            bind_settings.dim as i32,
-            bind_settings.data_type,
-            bind_settings.normalize,
-            bind_settings.stride,
-            bind_settings.offset,
-        );
+    /// Write the stored data from each bound buffer to the passed in context.
+    pub fn write_buffer_data(&self, ctx: &Gl) -> Result<(), String> {
+        ctx.bind_vertex_array(Some(&self.vao));
+        self.buffer_info
+            .write_attribute_data(ctx, self.program_data())
     }
 
-    /// Write the stored data from each bound buffer to the passed in context.
+    fn debug_draw_face_normals(&self, ctx: &Gl) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Free the item's buffers and vertex arrays on the GPU. The item must
+    /// be out of the render queues first.
+    pub fn delete_gl_resources(&self, ctx: &Gl) {
+        self.buffer_info.delete_buffers();
+        ctx.delete_vertex_array(Some(&self.vao));
+        if let Some(vao) = &self.face_normal_vao {
+            ctx.delete_vertex_array(Some(vao));
+        }
+    }
+}
+
+/// Key of a single instance within an `InstancedRenderItem`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct InstanceKey(pub usize);
+
+#[derive(Debug)]
+struct InstanceData {
+    tf: Rc<RefCell<Transform>>,
+    color: (f32, f32, f32, f32),
+}
+
+/// A mesh uploaded once and drawn many times with a single draw call.
+/// Each instance supplies its own transform and colour through instanced
+/// attributes.
+#[derive(Debug)]
+pub struct InstancedRenderItem {
+    program_data: ProgramData,
+    buffer_info: BufferInfo,
+    enabled: bool,
+    vao: GlVao,
+    draw_type: u32,
+    instances: RefCell<Slab<InstanceData>>,
+    matrix_buffer: GlBuffer,
+    color_buffer: GlBuffer,
+    /// Whether the instance matrices need uploading again.
+    matrices_dirty: Cell<bool>,
+    /// Whether instances were added or removed since the last upload.
+    instances_dirty: Cell<bool>,
+}
+
+impl InstancedRenderItem {
+    /// Per instance model matrix. Occupies four attribute locations.
+    pub const MATRIX_ATTRIBUTE: &'static str = "a_instanceMatrix";
+    /// Per instance RGBA colour.
+    pub const COLOR_ATTRIBUTE: &'static str = "a_instanceColor";
+
+    pub fn new(
+        ctx: &Gl,
+        program_data: ProgramData,
+        buffer_info: BufferInfo,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            vao: ctx.create_vertex_array().ok_or("Could not create VAO")?,
+            matrix_buffer: ctx
+                .create_buffer()
+                .ok_or("Could not create instance matrix buffer")?,
+            color_buffer: ctx
+                .create_buffer()
+                .ok_or("Could not create instance color buffer")?,
+            program_data,
+            buffer_info,
+            enabled: true,
+            draw_type: Gl::TRIANGLES,
+            instances: RefCell::new(Slab::new()),
+            matrices_dirty: Cell::new(true),
+            instances_dirty: Cell::new(true),
+        })
+    }
+
+    /// Draw another copy of the mesh, following the given transform.
+    pub fn add_instance(
+        &self,
+        tf: Rc<RefCell<Transform>>,
+        color: (f32, f32, f32, f32),
+    ) -> InstanceKey {
+        self.instances_dirty.set(true);
+        InstanceKey(
+            self.instances
+                .borrow_mut()
+                .insert(InstanceData { tf, color }),
+        )
+    }
+
+    /// Stop drawing an instance. Returns false if the key was not present.
+    pub fn remove_instance(&self, key: InstanceKey) -> bool {
+        let mut instances = self.instances.borrow_mut();
+        if instances.contains(key.0) {
+            instances.remove(key.0);
+            self.instances_dirty.set(true);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Upload the instance matrices again on the next draw, as an
+    /// instance's transform changed.
+    pub fn mark_moved(&self) {
+        self.matrices_dirty.set(true);
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.instances.borrow().len()
+    }
+
+    pub fn program_data(&self) -> &ProgramData {
+        &self.program_data
+    }
+
+    pub fn get_buffer_info(&self) -> &BufferInfo {
+        &self.buffer_info
+    }
+
+    /// Upload the shared mesh and set up the per instance attributes.
     pub fn write_buffer_data(&self, ctx: &Gl) -> Result<(), String> {
         ctx.bind_vertex_array(Some(&self.vao));
-        for (buffer_name, bind) in self.buffer_info.get_buffers().iter() {
-            let attr_loc = self
-                .program_data()
-                .attributes
-                .get(buffer_name)
-                .ok_or(format!("Buffer attribute {} not found", buffer_name))?;
-            ctx.bind_buffer(Gl::ARRAY_BUFFER, Some(&bind.webgl_buffer));
-            ctx.buffer_data_with_opt_array_buffer(
-                Gl::ARRAY_BUFFER,
-                Some(&bind.data),
-                Gl::STATIC_DRAW,
+        self.buffer_info
+            .write_attribute_data(ctx, &self.program_data)?;
+
+        let GlAttrLoc(matrix_loc) = *self
+            .program_data
+            .attributes
+            .get(Self::MATRIX_ATTRIBUTE)
+            .ok_or(format!("Attribute {} not found", Self::MATRIX_ATTRIBUTE))?;
+        ctx.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.matrix_buffer));
+        // A mat4 attribute is read as four consecutive vec4 columns.
+        const COLUMN_BYTES: i32 = 4 * 4;
+        for col in 0..4 {
+            let loc = (matrix_loc + col) as u32;
+            ctx.enable_vertex_attrib_array(loc);
+            ctx.vertex_attrib_pointer_with_i32(
+                loc,
+                4,
+                Gl::FLOAT,
+                false,
+                4 * COLUMN_BYTES,
+                col * COLUMN_BYTES,
             );
-            Self::setup_vertex_attrib(ctx, attr_loc, &bind.settings);
+            ctx.vertex_attrib_divisor(loc, 1);
         }
+
+        let GlAttrLoc(color_loc) = *self
+            .program_data
+            .attributes
+            .get(Self::COLOR_ATTRIBUTE)
+            .ok_or(format!("Attribute {} not found", Self::COLOR_ATTRIBUTE))?;
+        ctx.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.color_buffer));
+        ctx.enable_vertex_attrib_array(color_loc as u32);
+        ctx.vertex_attrib_pointer_with_i32(color_loc as u32, 4, Gl::FLOAT, false, 0, 0);
+        ctx.vertex_attrib_divisor(color_loc as u32, 1);
         Ok(())
     }
 
-    fn debug_draw_face_normals(&self, ctx: &Gl) -> Result<(), String> {
-        Ok(())
+    /// Copy the instance transforms and colours in to the instance
+    /// buffers, if they changed since the last call.
+    pub fn write_instance_data(&self, ctx: &Gl) {
+        let instances_dirty = self.instances_dirty.replace(false);
+        let matrices_dirty = self.matrices_dirty.replace(false) || instances_dirty;
+        let instances = self.instances.borrow();
+        if matrices_dirty {
+            let mut matrix_data: Vec<f32> = Vec::with_capacity(instances.len() * 16);
+            let mut tmp_mat = na::Matrix4::zeros();
+            for (_, instance) in instances.iter() {
+                instance.tf.borrow().world_mat_into(&mut tmp_mat);
+                matrix_data.extend(tmp_mat.iter());
+            }
+            ctx.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.matrix_buffer));
+            ctx.buffer_data_with_opt_array_buffer(
+                Gl::ARRAY_BUFFER,
+                Some(&matrix_data.as_buffer()),
+                Gl::DYNAMIC_DRAW,
+            );
+        }
+        if instances_dirty {
+            let mut color_data: Vec<f32> = Vec::with_capacity(instances.len() * 4);
+            for (_, instance) in instances.iter() {
+                let (r, g, b, a) = instance.color;
+                color_data.extend_from_slice(&[r, g, b, a]);
+            }
+            ctx.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.color_buffer));
+            ctx.buffer_data_with_opt_array_buffer(
+                Gl::ARRAY_BUFFER,
+                Some(&color_data.as_buffer()),
+                Gl::DYNAMIC_DRAW,
+            );
+        }
     }
 }
 
 pub trait Renderer {
     /// Render all items in the passed in queues on to the WebGl2 Context
-    fn render_all(&self, queues: &mut RenderableQueues);
+    fn render_all(&self, queues: &mut RenderableQueues, lighting: &Lighting);
     /// Return an immutable reference to the internal context.
     fn get_ctx(&self) -> &Gl;
     /// Conduct the first time draw set up by loading verts into the array buffer.
@@ -453,8 +823,9 @@ pub trait Renderer {
         item.write_buffer_data(self.get_ctx())
     }
 
-    fn set_camera_tf(&mut self, camera_tf: Rc<RefCell<Transform>>);
-    fn get_camera_tf(&self) -> Rc<RefCell<Transform>>;
+    /// Replace the camera used for subsequent frames.
+    fn set_camera(&self, camera: Rfc<Camera>);
+    fn get_camera(&self) -> Rfc<Camera>;
 
     /// Create new program data from a passed in context.
     fn new_program_data(
@@ -481,6 +852,7 @@ pub trait Renderer {
         }
         let v: BTreeMap<String, _> = Default::default();
         ProgramData {
+            program: program.clone(),
             uniforms: u,
             attributes: a,
             vaos: v,
@@ -495,39 +867,100 @@ enum RenderError {
     FailedDraw { info: String },
 }
 
+/// Mapping from camera space on to clip space. Both projections
+/// look down the camera's -Z axis.
+#[derive(Debug, Clone, Copy)]
+pub enum Projection {
+    /// Parallel projection of a `view_width` by `view_height` box,
+    /// `clip_depth` units deep.
+    Orthographic {
+        view_width: f32,
+        view_height: f32,
+        clip_depth: f32,
+    },
+    /// Pinhole camera projection with a vertical field of view.
+    Perspective {
+        fov_y: Angle<f32>,
+        aspect: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl Projection {
+    pub fn to_mat4(self) -> na::Matrix4<f32> {
+        match self {
+            Projection::Orthographic {
+                view_width,
+                view_height,
+                clip_depth,
+            } => na::Orthographic3::new(
+                -view_width * 0.5,
+                view_width * 0.5,
+                -view_height * 0.5,
+                view_height * 0.5,
+                -clip_depth * 0.5,
+                clip_depth * 0.5,
+            )
+            .to_homogeneous(),
+            Projection::Perspective {
+                fov_y,
+                aspect,
+                near,
+                far,
+            } => na::Perspective3::new(aspect, fov_y.as_rad(), near, far).to_homogeneous(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub struct RendererOrtho3D {
+pub struct Renderer3D {
     ctx: Rc<Gl>,
-    view_height: f32,
-    view_width: f32,
-    projection_mat: na::Matrix4<f32>,
-    camera_tf: Rc<RefCell<Transform>>,
+    camera: RefCell<Rfc<Camera>>,
     combined_camera_mat: RefCell<na::Matrix4<f32>>,
+    /// World position of the camera, for specular highlights.
+    camera_position: RefCell<Vector3<f32>>,
     tmp_mat_a: RefCell<na::Matrix4<f32>>,
     tmp_mat_b: RefCell<na::Matrix4<f32>>,
     tmp_mat_c: RefCell<na::Matrix4<f32>>,
+    /// Bound to diffuse samplers of items without a texture of their own.
+    default_texture: Rc<Texture>,
 }
 
-impl RendererOrtho3D {
-    pub fn new(ctx: Rc<Gl>, view_width: f32, view_height: f32, clip_depth: f32) -> Self {
+impl Renderer3D {
+    pub fn new(ctx: Rc<Gl>, camera: Rfc<Camera>) -> Self {
         ctx.enable(Gl::CULL_FACE); // Cull back faces
         ctx.enable(Gl::DEPTH_TEST); // Use depth to determine polygon draw ordering.
 
+        let default_texture =
+            Rc::new(Texture::white(&ctx).expect("Unable to create default texture"));
         Self {
             ctx,
-            projection_mat: na::Matrix4::<f32>::from_columns(&[
-                na::Vector4::new(2. / view_width, 0., 0., 0.),
-                na::Vector4::new(0., -2. / view_height, 0., 0.),
-                na::Vector4::new(0., 0., 2. / clip_depth, 0.),
-                na::Vector4::new(-1., 1., 0., 1.),
-            ]),
-            camera_tf: Rc::new(RefCell::new(Transform::identity())),
-            view_height,
-            view_width,
+            camera: RefCell::new(camera),
             combined_camera_mat: RefCell::new(na::Matrix4::zeros()),
+            camera_position: RefCell::new(Vector3::zeros()),
             tmp_mat_a: RefCell::new(na::Matrix4::zeros()),
             tmp_mat_b: RefCell::new(na::Matrix4::zeros()),
             tmp_mat_c: RefCell::new(na::Matrix4::zeros()),
+            default_texture,
+        }
+    }
+
+    /// Draw an item from the forward or reverse queue, if it's enabled,
+    /// and note whether it needs setting up again next time.
+    fn draw_queued_item(&self, item_tup: &mut (DrawnStatus, Rc<RenderItem>), lighting: &Lighting) {
+        if !item_tup.1.enabled {
+            return;
+        }
+        match self.draw_item(item_tup, &self.combined_camera_mat.borrow(), lighting) {
+            Ok(()) => {
+                item_tup.0 = if item_tup.1.always_redraw {
+                    DrawnStatus::NeedsDraw
+                } else {
+                    DrawnStatus::Drawn
+                }
+            }
+            Err(e) => js_bindings::error(&format!("Error: {:?}", e)),
         }
     }
 
@@ -535,9 +968,14 @@ impl RendererOrtho3D {
         &self,
         item_tup: &(DrawnStatus, Rc<RenderItem>),
         combined_camera_mat: &na::Matrix4<f32>,
+        lighting: &Lighting,
     ) -> Result<(), RenderError> {
         let (drawn_status, item) = item_tup;
         let borrowed_tf = item.tf.borrow();
+        self.ctx.use_program(Some(item.program_data().program()));
+        self.apply_lighting(item.program_data(), lighting);
+        self.apply_material(item.program_data(), &item.material);
+        self.bind_textures(item.program_data(), &item.textures);
         self.get_ctx().bind_vertex_array(Some(&item.vao));
         match drawn_status {
             DrawnStatus::NeedsDraw => {
@@ -552,6 +990,15 @@ impl RendererOrtho3D {
                 self.apply_tf(item, &borrowed_tf, &combined_camera_mat)?;
             }
         }
+        if let Some(indices) = item.get_buffer_info().get_indices() {
+            self.ctx.draw_elements_with_i32(
+                item.draw_type,
+                indices.len() as i32,
+                indices.index_type(),
+                0,
+            );
+            return Ok(());
+        }
         let components: i32 = item
             .get_buffer_size("a_position")
             .ok_or(RenderError::NoBufferSize {
@@ -563,6 +1010,143 @@ impl RendererOrtho3D {
         Ok(())
     }
 
+    fn draw_instanced_item(
+        &self,
+        item_tup: &(DrawnStatus, Rc<InstancedRenderItem>),
+        combined_camera_mat: &na::Matrix4<f32>,
+        lighting: &Lighting,
+    ) -> Result<(), RenderError> {
+        let (drawn_status, item) = item_tup;
+        let instance_count = item.instance_count() as i32;
+        if instance_count == 0 {
+            return Ok(());
+        }
+        self.ctx.use_program(Some(item.program_data().program()));
+        self.apply_lighting(item.program_data(), lighting);
+        self.apply_material(item.program_data(), &MaterialUniforms::default());
+        self.bind_textures(item.program_data(), &[]);
+        self.ctx.bind_vertex_array(Some(&item.vao));
+        if let DrawnStatus::NeedsDraw = drawn_status {
+            item.write_buffer_data(&self.ctx)
+                .map_err(|e| RenderError::FailedDraw { info: e })?;
+        }
+        item.write_instance_data(&self.ctx);
+
+        // Instances carry their own model matrix, so only the camera is
+        // applied through the uniform.
+        const U_NAME: &str = "u_transformationMatrix";
+        let loc =
+            item.program_data()
+                .uniforms
+                .get(U_NAME)
+                .ok_or(RenderError::FailedToGetUniformLoc {
+                    info: U_NAME.into(),
+                })?;
+        let val_ref: Vec<f32> = combined_camera_mat.iter().copied().collect();
+        self.ctx
+            .uniform_matrix4fv_with_f32_array(Some(loc), false, &val_ref);
+
+        if let Some(indices) = item.get_buffer_info().get_indices() {
+            self.ctx.draw_elements_instanced_with_i32(
+                item.draw_type,
+                indices.len() as i32,
+                indices.index_type(),
+                0,
+                instance_count,
+            );
+        } else {
+            let bind = item
+                .get_buffer_info()
+                .get_buffers()
+                .get("a_position")
+                .ok_or(RenderError::NoBufferSize {
+                    info: "a_position".into(),
+                })?;
+            let vertex_count = (bind.len() / bind.settings.dim as u32) as i32;
+            self.ctx
+                .draw_arrays_instanced(item.draw_type, 0, vertex_count, instance_count);
+        }
+        Ok(())
+    }
+
+    /// Upload the world's lights to the program, if it reads them.
+    fn apply_lighting(&self, program_data: &ProgramData, lighting: &Lighting) {
+        let uniforms = &program_data.uniforms;
+        if let Some(loc) = uniforms.get("u_ambientLight") {
+            self.ctx
+                .uniform3fv_with_f32_array(Some(loc), lighting.ambient.as_slice());
+        }
+        let (directions, colors) = lighting.directional_uniform_data();
+        if let Some(loc) = uniforms.get("u_dirLightCount") {
+            self.ctx.uniform1i(Some(loc), (directions.len() / 3) as i32);
+        }
+        if !directions.is_empty() {
+            if let Some(loc) = uniforms.get("u_dirLightDirections") {
+                self.ctx.uniform3fv_with_f32_array(Some(loc), &directions);
+            }
+            if let Some(loc) = uniforms.get("u_dirLightColors") {
+                self.ctx.uniform3fv_with_f32_array(Some(loc), &colors);
+            }
+        }
+        let (positions, colors, ranges) = lighting.point_uniform_data();
+        if let Some(loc) = uniforms.get("u_pointLightCount") {
+            self.ctx.uniform1i(Some(loc), ranges.len() as i32);
+        }
+        if !ranges.is_empty() {
+            if let Some(loc) = uniforms.get("u_pointLightPositions") {
+                self.ctx.uniform3fv_with_f32_array(Some(loc), &positions);
+            }
+            if let Some(loc) = uniforms.get("u_pointLightColors") {
+                self.ctx.uniform3fv_with_f32_array(Some(loc), &colors);
+            }
+            if let Some(loc) = uniforms.get("u_pointLightRanges") {
+                self.ctx.uniform1fv_with_f32_array(Some(loc), &ranges);
+            }
+        }
+    }
+
+    /// Upload an item's material, and the camera position specular
+    /// highlights depend on, if the program reads them.
+    fn apply_material(&self, program_data: &ProgramData, material: &MaterialUniforms) {
+        let uniforms = &program_data.uniforms;
+        if let Some(loc) = uniforms.get("u_cameraPosition") {
+            self.ctx
+                .uniform3fv_with_f32_array(Some(loc), self.camera_position.borrow().as_slice());
+        }
+        if let Some(loc) = uniforms.get("u_materialAmbient") {
+            let (r, g, b) = material.ambient;
+            self.ctx.uniform3f(Some(loc), r, g, b);
+        }
+        if let Some(loc) = uniforms.get("u_materialSpecular") {
+            let (r, g, b) = material.specular;
+            self.ctx.uniform3f(Some(loc), r, g, b);
+        }
+        if let Some(loc) = uniforms.get("u_materialShininess") {
+            self.ctx.uniform1f(Some(loc), material.shininess);
+        }
+    }
+
+    /// Bind each texture to its own unit and point its sampler at it.
+    fn bind_textures(&self, program_data: &ProgramData, textures: &[(String, Rc<Texture>)]) {
+        let uniforms = &program_data.uniforms;
+        let mut unit = 0;
+        for (name, texture) in textures {
+            if let Some(loc) = uniforms.get(name) {
+                texture.bind(&self.ctx, unit);
+                self.ctx.uniform1i(Some(loc), unit as i32);
+                unit += 1;
+            }
+        }
+        // Samplers with nothing bound read black, so fall back to white.
+        let has_diffuse = textures
+            .iter()
+            .any(|(name, _)| name == DIFFUSE_TEXTURE_UNIFORM);
+        if let (false, Some(loc)) = (has_diffuse, uniforms.get(DIFFUSE_TEXTURE_UNIFORM)) {
+            self.default_texture.bind(&self.ctx, unit);
+            self.ctx.uniform1i(Some(loc), unit as i32);
+        }
+    }
+
     fn apply_tf(
         &self,
         item: &RenderItem,
@@ -575,11 +1159,30 @@ impl RendererOrtho3D {
                 let loc: Option<&GlULoc> = Some(&*loc_rc);
                 let mut tmp_mat_a = self.tmp_mat_a.borrow_mut();
                 let mut tmp_mat_b = self.tmp_mat_c.borrow_mut();
-                tf.to_mat4_into(&mut tmp_mat_a);
+                tf.world_mat_into(&mut tmp_mat_a);
                 combined_camera_mat.mul_to(&tmp_mat_a, &mut tmp_mat_b);
                 let val_ref: Vec<f32> = tmp_mat_b.iter().copied().collect();
                 self.ctx
                     .uniform_matrix4fv_with_f32_array(loc, false, &val_ref);
+
+                // Lit programs also need the model transform on its own.
+                let uniforms = &item.program_data().uniforms;
+                if let Some(model_loc) = uniforms.get("u_model") {
+                    let val_ref: Vec<f32> = tmp_mat_a.iter().copied().collect();
+                    self.ctx
+                        .uniform_matrix4fv_with_f32_array(Some(model_loc), false, &val_ref);
+                }
+                if let Some(normal_loc) = uniforms.get("u_normalMatrix") {
+                    let normal_mat = tmp_mat_a
+                        .fixed_slice::<na::U3, na::U3>(0, 0)
+                        .into_owned()
+                        .try_inverse()
+                        .unwrap_or_else(na::Matrix3::identity)
+                        .transpose();
+                    let val_ref: Vec<f32> = normal_mat.iter().copied().collect();
+                    self.ctx
+                        .uniform_matrix3fv_with_f32_array(Some(normal_loc), false, &val_ref);
+                }
                 Ok(())
             }
             _ => Err(RenderError::FailedToGetUniformLoc {
@@ -587,54 +1190,69 @@ impl RendererOrtho3D {
             }),
         }
     }
-
-    fn camera_tf_to_mat(&self, camera_tf: &Transform) -> na::Matrix4<f32> {
-        let shift_vec = Vector3::new(self.view_width * 0.5, self.view_height * 0.5, 0.0);
-        camera_tf
-            .to_mat4()
-            .try_inverse()
-            .expect("Unreachable")
-            .append_translation(&shift_vec)
-    }
 }
 
-impl Renderer for RendererOrtho3D {
-    fn render_all(&self, queues: &mut RenderableQueues) {
+impl Renderer for Renderer3D {
+    fn render_all(&self, queues: &mut RenderableQueues, lighting: &Lighting) {
         self.ctx.clear(Gl::COLOR_BUFFER_BIT | Gl::DEPTH_BUFFER_BIT);
-        let camera_tf = &self.camera_tf.borrow();
-        let camera_tf_mat = self.camera_tf_to_mat(camera_tf);
-        {
-            let mut combined_camera_mat = self.combined_camera_mat.borrow_mut();
-            self.projection_mat
-                .mul_to(&camera_tf_mat, &mut combined_camera_mat);
-        }
+        let view_mat = {
+            let camera_rc = self.get_camera();
+            let camera = camera_rc.borrow();
+            let (x, y, width, height) = camera
+                .get_viewport()
+                .gl_rect(self.ctx.drawing_buffer_height() as f32);
+            self.ctx.viewport(x, y, width, height);
+            *self.combined_camera_mat.borrow_mut() = camera.view_projection_mat();
+            *self.camera_position.borrow_mut() = camera.get_tf_rc().borrow().get_world_position();
+            camera.view_mat()
+        };
         for item_tup in queues.forward_queue.iter_mut() {
+            if !item_tup.1.material.is_translucent() {
+                self.draw_queued_item(item_tup, lighting);
+            }
+        }
+        for item_tup in queues.instanced_queue.iter_mut() {
             if item_tup.1.enabled {
-                match self.draw_item(item_tup, &self.combined_camera_mat.borrow()) {
-                    Ok(()) => {
-                        item_tup.0 = if item_tup.1.always_redraw {
-                            DrawnStatus::NeedsDraw
-                        } else {
-                            DrawnStatus::Drawn
-                        }
-                    }
+                match self.draw_instanced_item(
+                    item_tup,
+                    &self.combined_camera_mat.borrow(),
+                    lighting,
+                ) {
+                    Ok(()) => item_tup.0 = DrawnStatus::Drawn,
                     Err(e) => js_bindings::error(&format!("Error: {:?}", e)),
                 }
             }
         }
         for item_tup in queues.reverse_queue.iter_mut().rev() {
-            if item_tup.1.enabled {
-                match self.draw_item(item_tup, &self.combined_camera_mat.borrow()) {
-                    Ok(()) => {
-                        item_tup.0 = if item_tup.1.always_redraw {
-                            DrawnStatus::NeedsDraw
-                        } else {
-                            DrawnStatus::Drawn
-                        }
-                    }
-                    Err(e) => js_bindings::error(&format!("Error: {:?}", e)),
-                }
+            if !item_tup.1.material.is_translucent() {
+                self.draw_queued_item(item_tup, lighting);
+            }
+        }
+
+        // Blend translucent items over everything opaque, furthest first,
+        // without hiding each other through the depth buffer.
+        let mut translucent: Vec<(f32, &mut (DrawnStatus, Rc<RenderItem>))> = queues
+            .forward_queue
+            .iter_mut()
+            .chain(queues.reverse_queue.iter_mut())
+            .filter(|(_, item)| item.material.is_translucent())
+            .map(|item_tup| {
+                let position = item_tup.1.tf.borrow().get_world_position();
+                // Camera space z is negative in front of the camera.
+                let depth = view_mat.transform_point(&position.into())[2];
+                (depth, item_tup)
+            })
+            .collect();
+        if !translucent.is_empty() {
+            translucent.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+            self.ctx.enable(Gl::BLEND);
+            self.ctx.blend_func(Gl::SRC_ALPHA, Gl::ONE_MINUS_SRC_ALPHA);
+            self.ctx.depth_mask(false);
+            for (_, item_tup) in translucent {
+                self.draw_queued_item(item_tup, lighting);
             }
+            self.ctx.depth_mask(true);
+            self.ctx.disable(Gl::BLEND);
         }
         // Flush, based on WebGl1 best practices.
         self.ctx.flush();
@@ -644,11 +1262,11 @@ impl Renderer for RendererOrtho3D {
         &self.ctx
     }
 
-    fn set_camera_tf(&mut self, camera_tf: Rc<RefCell<Transform>>) {
-        self.camera_tf = camera_tf;
+    fn set_camera(&self, camera: Rfc<Camera>) {
+        *self.camera.borrow_mut() = camera;
     }
 
-    fn get_camera_tf(&self) -> Rc<RefCell<Transform>> {
-        self.camera_tf.clone()
+    fn get_camera(&self) -> Rfc<Camera> {
+        self.camera.borrow().clone()
     }
 }
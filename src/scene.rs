@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+
+use na::Vector3;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::maths_utils::EulerAngles3D;
+use crate::mesh::wavefront_mtl::into_materials;
+use crate::mesh::wavefront_obj::{into_indexed_mesh, into_mesh_set};
+use crate::mesh::{IndexedMesh, MeshSet};
+use crate::rendering::ProgramData;
+use crate::transform::Transform;
+use crate::world_object::Material;
+
+/// Text formats scenes can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneFormat {
+    Json,
+    Ron,
+}
+
+impl SceneFormat {
+    /// Pick the format from a `.json` or `.ron` file extension.
+    pub fn from_url(url: &str) -> Result<Self, String> {
+        if url.ends_with(".json") {
+            Ok(SceneFormat::Json)
+        } else if url.ends_with(".ron") {
+            Ok(SceneFormat::Ron)
+        } else {
+            Err(format!("Unknown scene format for {}", url))
+        }
+    }
+}
+
+/// A saved tree of world objects.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneData {
+    /// Objects without a parent.
+    #[serde(default)]
+    pub objects: Vec<SceneObject>,
+}
+
+impl SceneData {
+    pub fn from_text(data: &str, format: SceneFormat) -> Result<Self, String> {
+        match format {
+            SceneFormat::Json => serde_json::from_str(data).map_err(|e| e.to_string()),
+            SceneFormat::Ron => ron::from_str(data).map_err(|e| e.to_string()),
+        }
+    }
+
+    pub fn to_text(&self, format: SceneFormat) -> Result<String, String> {
+        match format {
+            SceneFormat::Json => serde_json::to_string_pretty(self).map_err(|e| e.to_string()),
+            SceneFormat::Ron => {
+                ron::ser::to_string_pretty(self, Default::default()).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    /// Return the url of every mesh the scene refers to.
+    pub fn mesh_urls(&self) -> Vec<String> {
+        fn collect(objects: &[SceneObject], urls: &mut Vec<String>) {
+            for obj in objects {
+                if let Some(mesh) = &obj.mesh {
+                    if !urls.contains(&mesh.url) {
+                        urls.push(mesh.url.clone());
+                    }
+                }
+                collect(&obj.children, urls);
+            }
+        }
+        let mut urls = vec![];
+        collect(&self.objects, &mut urls);
+        urls
+    }
+}
+
+/// A saved world object and its children.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SceneObject {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default)]
+    pub transform: TransformData,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshRef>,
+    /// Registered component data, by the name the type was registered as.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub components: BTreeMap<String, Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SceneObject>,
+}
+
+/// A transform relative to the parent, with rotation as Euler angles in
+/// degrees so it's easy to write by hand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformData {
+    pub position: [f32; 3],
+    /// Roll, pitch and yaw in degrees.
+    pub rotation: [f32; 3],
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformData {
+    fn default() -> Self {
+        Self {
+            position: [0., 0., 0.],
+            rotation: [0., 0., 0.],
+            scale: [1., 1., 1.],
+        }
+    }
+}
+
+impl TransformData {
+    pub fn from_transform(tf: &Transform) -> Self {
+        let rot = tf.get_euler_rotation();
+        Self {
+            position: tf.get_position().into(),
+            rotation: [rot.roll.as_deg(), rot.pitch.as_deg(), rot.yaw.as_deg()],
+            scale: tf.get_scale().into(),
+        }
+    }
+
+    pub fn to_transform(&self) -> Transform {
+        let [roll, pitch, yaw] = self.rotation;
+        let mut tf = Transform::identity();
+        tf.set_position(Vector3::from(self.position))
+            .set_euler_rotation(EulerAngles3D::from_deg(roll, pitch, yaw))
+            .set_scale(Vector3::from(self.scale));
+        tf
+    }
+}
+
+/// A mesh drawn by an object, by the url of its OBJ file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MeshRef {
+    pub url: String,
+    /// Name of the GL program to draw with.
+    pub program: String,
+    /// Name of a material in the OBJ file's material library.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    #[serde(default, skip_serializing_if = "MeshLayout::is_single")]
+    pub layout: MeshLayout,
+}
+
+/// How the contents of an OBJ file are drawn by the object referring to
+/// it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MeshLayout {
+    /// The whole file as one mesh.
+    #[default]
+    Single,
+    /// The whole file as one instance of a mesh shared by every object
+    /// instancing the same `MeshRef`. Only the material's colour is used.
+    Instanced,
+    /// A child per object and group in the file, as made by
+    /// `WorldState::add_mesh_set`, with materials from the file's own
+    /// material library. The children are made again on loading, so
+    /// aren't saved.
+    Split,
+}
+
+impl MeshLayout {
+    fn is_single(&self) -> bool {
+        *self == MeshLayout::Single
+    }
+}
+
+/// OBJ and MTL files parsed while loading a scene, by url, so objects
+/// sharing a file don't each parse it again.
+#[derive(Default)]
+pub struct ParsedMeshes {
+    meshes: BTreeMap<String, Rc<IndexedMesh>>,
+    mesh_sets: BTreeMap<String, Rc<MeshSet>>,
+    materials: BTreeMap<String, Rc<BTreeMap<String, Material>>>,
+}
+
+impl ParsedMeshes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The OBJ file at `url`, flattened in to one mesh.
+    pub fn mesh(&mut self, url: &str, data: &str) -> Result<Rc<IndexedMesh>, String> {
+        parse_once(&mut self.meshes, url, || into_indexed_mesh(data))
+    }
+
+    /// The OBJ file at `url`, keeping its objects and groups.
+    pub fn mesh_set(&mut self, url: &str, data: &str) -> Result<Rc<MeshSet>, String> {
+        parse_once(&mut self.mesh_sets, url, || into_mesh_set(data))
+    }
+
+    /// The materials in the MTL file at `url`, by name.
+    pub fn materials(
+        &mut self,
+        url: &str,
+        data: &str,
+    ) -> Result<Rc<BTreeMap<String, Material>>, String> {
+        parse_once(&mut self.materials, url, || into_materials(data))
+    }
+}
+
+fn parse_once<T>(
+    parsed: &mut BTreeMap<String, Rc<T>>,
+    url: &str,
+    parse: impl FnOnce() -> Result<T, String>,
+) -> Result<Rc<T>, String> {
+    if let Some(found) = parsed.get(url) {
+        return Ok(found.clone());
+    }
+    let found = Rc::new(parse().map_err(|e| format!("Unable to read {}: {}", url, e))?);
+    parsed.insert(url.to_owned(), found.clone());
+    Ok(found)
+}
+
+/// Loaded assets a scene's references are resolved against.
+pub struct SceneAssets<'a> {
+    /// Text of each loaded OBJ and MTL file, by url.
+    pub mesh_datas: &'a BTreeMap<String, String>,
+    pub programs: &'a BTreeMap<String, ProgramData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_scene() -> SceneData {
+        SceneData {
+            objects: vec![SceneObject {
+                name: "house".into(),
+                tags: vec!["building".to_owned()].into_iter().collect(),
+                transform: TransformData {
+                    position: [1., 2., 3.],
+                    rotation: [90., 0., 45.],
+                    ..Default::default()
+                },
+                mesh: Some(MeshRef {
+                    url: "assets/cottage_1.obj".into(),
+                    program: "lit".into(),
+                    material: Some("Wood".into()),
+                    layout: MeshLayout::Instanced,
+                }),
+                components: vec![("health".to_owned(), serde_json::json!(10))]
+                    .into_iter()
+                    .collect(),
+                children: vec![SceneObject {
+                    name: "chimney".into(),
+                    ..Default::default()
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn scenes_round_trip() {
+        let scene = example_scene();
+        for &format in &[SceneFormat::Json, SceneFormat::Ron] {
+            let text = scene.to_text(format).unwrap();
+            assert_eq!(SceneData::from_text(&text, format).unwrap(), scene);
+        }
+    }
+
+    #[test]
+    fn transforms_convert() {
+        let data = example_scene().objects[0].transform.clone();
+        let back = TransformData::from_transform(&data.to_transform());
+        for (a, b) in data.rotation.iter().zip(back.rotation.iter()) {
+            assert!((a - b).abs() < 1e-3, "{:?} != {:?}", data, back);
+        }
+        assert_eq!(back.position, data.position);
+    }
+
+    #[test]
+    fn reads_example_scene() {
+        let scene = SceneData::from_text(
+            include_str!("../www/assets/example_scene.ron"),
+            SceneFormat::Ron,
+        )
+        .unwrap();
+        assert_eq!(scene.mesh_urls(), vec!["assets/cube.obj".to_owned()]);
+        assert_eq!(scene.objects[0].children.len(), 2);
+    }
+
+    #[test]
+    fn meshes_are_parsed_once_per_url() {
+        let data = include_str!("../www/assets/cube.obj");
+        let mut parsed = ParsedMeshes::new();
+        let first = parsed.mesh("assets/cube.obj", data).unwrap();
+        let again = parsed.mesh("assets/cube.obj", "not an obj file").unwrap();
+        assert!(Rc::ptr_eq(&first, &again));
+    }
+}
@@ -4,27 +4,36 @@ use std::rc::Rc;
 use std::sync::{Arc, Mutex, RwLock};
 
 use na::Vector3;
-use rand::random;
 use wasm_bindgen::JsCast;
 use web_sys::{Document, HtmlCanvasElement, WebGl2RenderingContext as Gl, WebGlProgram};
 
+use crate::camera::{Camera, Viewport};
+use crate::clock::{Clock, FixedTimestep, FrameTimer, PerformanceClock};
+use crate::events::MouseMoved;
 use crate::geometry;
 use crate::inputs;
 use crate::inputs::Input; // Specifically for the trait.
 use crate::js_bindings;
 use crate::maths_utils::*;
 use crate::mesh;
+use crate::mesh::wavefront_obj;
 use crate::rendering::*;
-use crate::steppables::StepError::*;
-use crate::steppables::{StepError, Steppable};
-use crate::transform::Transform;
+use crate::scene::{MeshLayout, MeshRef, SceneAssets, SceneData, SceneFormat};
+use crate::texture::{self, Texture};
 use crate::util;
-use crate::world_object::{
-    Material, MeshComponent, RenderComponent, WorldObject3D, WorldObject3DInit,
-};
 use crate::world_state::WorldState;
 
-const FIXED_STEP_RATE: u64 = 1000 / 48;
+/// Game seconds between fixed steps, unless set otherwise.
+const FIXED_DELTA_TIME: f32 = 1.0 / 48.0;
+/// Most fixed steps run in one frame, unless set otherwise.
+const MAX_FIXED_STEPS: u32 = 5;
+
+/// Which projection the renderer is built with when binding a canvas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionMode {
+    Orthographic,
+    Perspective,
+}
 
 enum LoadInStage {
     NotLoading,
@@ -35,13 +44,18 @@ enum LoadInStage {
 pub struct GameLoop {
     state: Arc<Mutex<WorldState>>,
     shader_pg_data: BTreeMap<String, ProgramData>,
-    mesh_data_arcs: Vec<(String, Arc<Mutex<Option<String>>>)>,
+    mesh_data_arcs: Vec<(String, wavefront_obj::MeshDataCell)>,
+    texture_image_cells: Vec<(String, texture::ImageCell)>,
+    scene_urls: Vec<String>,
     load_in_stage: LoadInStage,
+    projection_mode: ProjectionMode,
     mesh_datas: BTreeMap<String, String>,
-    last_step_start_t: u64,
-    last_step_end_t: u64,
-    last_fixed_step_end_t: u64,
-    last_multistep_end_t: u64,
+    textures: BTreeMap<String, Rc<Texture>>,
+    timer: FrameTimer,
+    fixed_timestep: FixedTimestep,
+    paused: bool,
+    /// Whether to step through the next frame despite being paused.
+    step_once: bool,
 }
 
 impl GameLoop {
@@ -51,24 +65,79 @@ impl GameLoop {
             mesh_data_arcs: Default::default(),
             shader_pg_data: Default::default(),
             mesh_datas: Default::default(),
+            texture_image_cells: Default::default(),
+            scene_urls: Default::default(),
+            textures: Default::default(),
             load_in_stage: LoadInStage::NotLoading,
-            last_step_start_t: 0,
-            last_step_end_t: 0,
-            last_fixed_step_end_t: 0,
-            last_multistep_end_t: 0,
+            projection_mode: ProjectionMode::Orthographic,
+            timer: FrameTimer::new(PerformanceClock),
+            fixed_timestep: FixedTimestep::new(FIXED_DELTA_TIME, MAX_FIXED_STEPS),
+            paused: false,
+            step_once: false,
         }
     }
 
     /// Set the canvas object to render to.
     pub fn bind_canvas(&mut self, canvas: HtmlCanvasElement, ctx: Rc<Gl>) {
-        let ratio = util::canvas_aspect_ratio(&canvas);
-        let viewport_size = 30.;
-        let world_depth = 400.;
+        let viewport = Viewport::new(0., 0., canvas.width() as f32, canvas.height() as f32);
+        let mut camera = Camera::new(self.projection_for_canvas(&canvas), viewport);
+        self.position_camera(&mut camera);
+        let camera = Rc::new(RefCell::new(camera));
 
         let mut state_mg = self.state.lock().unwrap();
-        let renderer = RendererOrtho3D::new(ctx, viewport_size, viewport_size / ratio, world_depth);
-        state_mg.set_renderer(renderer);
+        state_mg.set_renderer(Renderer3D::new(ctx, camera.clone()));
         state_mg.set_canvas(Some(Arc::new(canvas)));
+        state_mg.add_camera(camera);
+    }
+
+    /// Switch between orthographic and perspective rendering.
+    /// Takes effect immediately if a canvas is already bound.
+    pub fn set_projection_mode(&mut self, mode: ProjectionMode) {
+        self.projection_mode = mode;
+        let state_mg = self.state.lock().unwrap();
+        if let (Some(camera), Some(canvas)) = (state_mg.get_main_camera(), state_mg.get_canvas()) {
+            let mut camera = camera.borrow_mut();
+            camera.set_projection(self.projection_for_canvas(&canvas));
+            self.position_camera(&mut camera);
+        }
+    }
+
+    pub fn get_projection_mode(&self) -> ProjectionMode {
+        self.projection_mode
+    }
+
+    fn projection_for_canvas(&self, canvas: &HtmlCanvasElement) -> Projection {
+        let ratio = util::canvas_aspect_ratio(canvas);
+        let viewport_size = 30.;
+        let world_depth = 400.;
+        match self.projection_mode {
+            ProjectionMode::Orthographic => Projection::Orthographic {
+                view_width: viewport_size,
+                view_height: viewport_size / ratio,
+                clip_depth: world_depth,
+            },
+            ProjectionMode::Perspective => Projection::Perspective {
+                fov_y: Angle::from_deg(45.),
+                aspect: ratio,
+                near: 0.1,
+                far: world_depth,
+            },
+        }
+    }
+
+    /// Place the camera for the projection mode. Orthographic mode keeps
+    /// the mapping scripts were written against, see `Camera::look_down_z`.
+    /// Perspective mode looks at the origin from -Z, with +y pointing down
+    /// the screen.
+    fn position_camera(&self, camera: &mut Camera) {
+        match self.projection_mode {
+            ProjectionMode::Orthographic => camera.look_down_z(),
+            ProjectionMode::Perspective => camera.look_at(
+                Vector3::new(0., 0., -40.),
+                Vector3::zeros(),
+                Vector3::new(0., -1., 0.),
+            ),
+        }
     }
 
     pub fn add_gl_program(&mut self, name: String, pg: WebGlProgram, location_names: &[String]) {
@@ -97,12 +166,31 @@ impl GameLoop {
         self.mesh_data_arcs.push((url, Default::default()));
     }
 
+    /// Load a `.json` or `.ron` scene, and every mesh it refers to. The
+    /// scene's objects are added during setup.
+    pub fn add_load_in_scene(&mut self, url: String) {
+        self.scene_urls.push(url.clone());
+        self.add_load_in_mesh(url);
+    }
+
+    pub fn add_load_in_texture(&mut self, url: String) {
+        self.texture_image_cells.push((url, Default::default()));
+    }
+
+    /// Return a texture loaded in through `add_load_in_texture`.
+    pub fn get_texture(&self, url: &str) -> Option<Rc<Texture>> {
+        self.textures.get(url).cloned()
+    }
+
     pub fn load_in(&mut self) -> Result<bool, String> {
         let out = match self.load_in_stage {
             LoadInStage::NotLoading => {
                 for (url, m) in self.mesh_data_arcs.iter_mut() {
                     *m = crate::mesh::wavefront_obj::get_mesh_data_from_url(url.to_owned());
                 }
+                for (url, cell) in self.texture_image_cells.iter_mut() {
+                    *cell = texture::get_image_from_url(url.to_owned());
+                }
                 self.load_in_stage = LoadInStage::Started;
                 false
             }
@@ -110,17 +198,30 @@ impl GameLoop {
                 let mut done = true;
                 for (url, m) in self.mesh_data_arcs.iter_mut() {
                     if let Ok(lock) = m.try_lock() {
-                        if let Some(data) = lock.as_ref() {
-                            self.mesh_datas.insert(url.to_owned(), data.to_owned());
-                        } else {
-                            done = false;
-                            break
+                        match lock.as_ref() {
+                            Some(Ok(data)) => {
+                                self.mesh_datas.insert(url.to_owned(), data.to_owned());
+                            }
+                            Some(Err(e)) => return Err(e.to_owned()),
+                            None => {
+                                done = false;
+                                break;
+                            }
                         }
                     } else {
                         done = false;
                         break
                     }
                 }
+                if done {
+                    done = self.queue_scene_meshes()?;
+                }
+                if done {
+                    done = self.queue_material_libraries();
+                }
+                if done {
+                    done = self.upload_loaded_textures()?;
+                }
                 if done {
                     self.load_in_stage = LoadInStage::Complete;
                 }
@@ -133,6 +234,73 @@ impl GameLoop {
         Ok(out)
     }
 
+    fn get_loaded_scene(&self, url: &str) -> Result<SceneData, String> {
+        let data = self
+            .mesh_datas
+            .get(url)
+            .ok_or(format!("Scene {} is not loaded", url))?;
+        SceneData::from_text(data, SceneFormat::from_url(url)?)
+            .map_err(|e| format!("Unable to read scene {}: {}", url, e))
+    }
+
+    /// Start fetching every mesh referred to by a loaded scene.
+    /// Returns true if there were none left to fetch.
+    fn queue_scene_meshes(&mut self) -> Result<bool, String> {
+        let mut queued = false;
+        for url in self.scene_urls.clone() {
+            for mesh_url in self.get_loaded_scene(&url)?.mesh_urls() {
+                if self.mesh_data_arcs.iter().all(|(u, _)| *u != mesh_url) {
+                    let arc = wavefront_obj::get_mesh_data_from_url(mesh_url.clone());
+                    self.mesh_data_arcs.push((mesh_url, arc));
+                    queued = true;
+                }
+            }
+        }
+        Ok(!queued)
+    }
+
+    /// Start fetching the material library of every loaded obj file that
+    /// refers to one. Returns true if there were none left to fetch.
+    fn queue_material_libraries(&mut self) -> bool {
+        let mut queued = false;
+        for (url, data) in self.mesh_datas.iter() {
+            let mtl_url = match wavefront_obj::material_library_url(url, data) {
+                Some(mtl_url) => mtl_url,
+                None => continue,
+            };
+            if self.mesh_data_arcs.iter().all(|(u, _)| *u != mtl_url) {
+                let arc = wavefront_obj::get_mesh_data_from_url(mtl_url.clone());
+                self.mesh_data_arcs.push((mtl_url, arc));
+                queued = true;
+            }
+        }
+        !queued
+    }
+
+    /// Create textures for every image that has finished loading.
+    /// Returns true once all of them have been created.
+    fn upload_loaded_textures(&mut self) -> Result<bool, String> {
+        let state_mg = self.state.lock().unwrap();
+        let renderer = state_mg
+            .get_renderer()
+            .ok_or("Must bind canvas before loading textures")?;
+        let mut done = true;
+        for (url, cell) in self.texture_image_cells.iter() {
+            if self.textures.contains_key(url) {
+                continue;
+            }
+            match cell.borrow().as_ref() {
+                Some(Ok(image)) => {
+                    let texture = Texture::from_image(renderer.get_ctx(), image)?;
+                    self.textures.insert(url.to_owned(), Rc::new(texture));
+                }
+                Some(Err(e)) => return Err(e.to_owned()),
+                None => done = false,
+            }
+        }
+        Ok(done)
+    }
+
     /// Conduct game setup, prior to start and normal stepping behaviour.
     pub fn setup(&mut self) -> Result<(), String> {
         let mut s = self.state.lock().unwrap();
@@ -144,18 +312,41 @@ impl GameLoop {
                 return Err("Canvas not bound for input.".into());
             }
         }
+        let block_ref = MeshRef {
+            url: "assets/cube.obj".into(),
+            program: "instanced_lit".into(),
+            material: None,
+            layout: MeshLayout::Instanced,
+        };
+        s.add_scripted_component(crate::game::spinning::Spinner);
         s.add_scripted_component(crate::game::blocks::BlockBehavior {
             program_data: self.shader_pg_data["vertex_color"].clone(),
-            mesh_data: self.mesh_datas["assets/cube.obj"].clone(),
+            instanced_program_data: self.shader_pg_data[&block_ref.program].clone(),
+            mesh_data: self.mesh_datas[&block_ref.url].clone(),
+            mesh_ref: block_ref,
+        });
+        let cottage_ref = MeshRef {
+            url: "assets/cottage_1.obj".into(),
+            program: "lit".into(),
+            material: None,
+            layout: MeshLayout::Split,
+        };
+        let cottage_data = self.mesh_datas[&cottage_ref.url].clone();
+        s.add_scripted_component(crate::game::cottage_scene::CottageMaker {
+            program_data: self.shader_pg_data[&cottage_ref.program].clone(),
+            material_data: wavefront_obj::material_library_url(&cottage_ref.url, &cottage_data)
+                .and_then(|mtl_url| self.mesh_datas.get(&mtl_url).cloned()),
+            mesh_data: cottage_data,
+            mesh_ref: cottage_ref,
         });
-        /*
-        s.add_scripted_component(TorusGen::new(
-            self.shader_pg_data
-                .get("vertex_color")
-                .expect("No vertex_color shader")
-                .clone(),
-        ));
-        */
+        for url in self.scene_urls.iter() {
+            let scene = self.get_loaded_scene(url)?;
+            let assets = SceneAssets {
+                mesh_datas: &self.mesh_datas,
+                programs: &self.shader_pg_data,
+            };
+            s.load_scene(&scene, &assets)?;
+        }
         Ok(())
     }
 
@@ -180,6 +371,10 @@ impl GameLoop {
                 match inputs::get_mouse_pos(bind_to_clone.as_ref(), &evt) {
                     Ok(new_input) => {
                         let mut state_mg = state.lock().unwrap();
+                        state_mg.publish(MouseMoved {
+                            view_x: new_input.get_mouse_view_x(),
+                            view_y: new_input.get_mouse_view_y(),
+                        });
                         state_mg.set_inputs(Some(new_input));
                     }
                     Err(_) => {
@@ -191,159 +386,115 @@ impl GameLoop {
         .map_err(|_| "Failed to add listener to canvas".into())
     }
 
-    pub fn start(&mut self) -> Result<(), String> {
-        let mut state_mg = self.state.lock().unwrap();
-        let pre_start_cbs = state_mg.scripted_components();
-        let mut error_buffer: StepError<String> = Ignore;
-        for s in pre_start_cbs {
-            s.borrow_mut()
-                .start(&mut *state_mg)
-                .or_else(|e| e.store_nonfatal(&mut error_buffer))
-                .or_else(|e| e.translate())?;
-        }
-        error_buffer.translate()
+    /// Time frames with `clock` rather than `performance.now()`.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) -> &mut Self {
+        self.timer = FrameTimer::new(clock);
+        self
     }
 
-    pub fn step(&mut self) -> Result<(), String> {
-        self.last_step_start_t = js_bindings::millis_now() as u64;
-        let mut state_mg = self.state.lock().unwrap();
-        let mut pre_step_cbs = state_mg.scripted_components();
-        state_mg.run_steps(&self, &mut pre_step_cbs)?;
-        self.last_step_end_t = js_bindings::millis_now() as u64;
-
-        // Needs to be i64 due to possible integer negative overflow
-        if self.last_step_end_t as i64 - self.last_fixed_step_end_t as i64 > FIXED_STEP_RATE as i64
-        {
-            state_mg.run_fixed_steps(&self, &mut pre_step_cbs)?;
-            self.last_fixed_step_end_t = js_bindings::millis_now() as u64;
-        }
-
-        let mut late_step_cbs = state_mg.scripted_components();
-        state_mg.run_late_steps(&self, &mut late_step_cbs)?;
-        if let Some(r) = state_mg.get_renderer() {
-            r.render_all(state_mg.get_rendqueue_mut())
-        }
+    /// Run fixed steps every `fixed_delta_time` game seconds, and at most
+    /// `max_steps` of them a frame.
+    pub fn set_fixed_timestep(&mut self, fixed_delta_time: f32, max_steps: u32) -> &mut Self {
+        self.fixed_timestep = FixedTimestep::new(fixed_delta_time, max_steps);
+        self
+    }
 
-        state_mg.inc_frame_count();
-        let new_multistep_end_t = js_bindings::millis_now() as u64;
-        // Needs to be i64 due to possible integer negative overflow
-        let cycle_time = new_multistep_end_t as i64 - self.last_multistep_end_t as i64;
-        state_mg.delta_time = match cycle_time {
-            0 => 0.5,
-            ct => 1.0 / ct as f32,
-        };
-        self.last_multistep_end_t = new_multistep_end_t;
-        Ok(())
+    /// Stop stepping the world, while still drawing it. Events are still
+    /// delivered, so subscribers see input as it happens.
+    pub fn pause(&mut self) {
+        self.paused = true;
     }
 
-    pub fn get_mesh_data(&self, idx: usize) {
-        
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step_once = false;
     }
-}
 
-fn set_rand_pos(tf: &mut Transform) {
-    tf.set_position(Vector3::new(
-        random::<f32>().abs() * 50.0,
-        random::<f32>().abs() * 50.0,
-        random::<f32>().abs() * 50.0,
-    ));
-}
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
 
-fn spin_tf(tf: &mut Transform, delta: EulerAngles3D<f32>, delta_time: f32) {
-    let mut new_rot = tf.get_euler_rotation();
-    new_rot.roll = Angle::from_rad(new_rot.roll.as_rad() + delta.roll.as_rad() * delta_time);
-    new_rot.pitch = Angle::from_rad(new_rot.pitch.as_rad() + delta.pitch.as_rad() * delta_time);
-    new_rot.yaw = Angle::from_rad(new_rot.yaw.as_rad() + delta.yaw.as_rad() * delta_time);
-    tf.set_euler_rotation(new_rot);
-}
+    /// While paused, step through the next frame as if one fixed step of
+    /// game time had passed.
+    pub fn step_once(&mut self) {
+        if self.paused {
+            self.step_once = true;
+        }
+    }
 
-#[derive(Clone)]
-struct TorusGen {
-    pub program_data: ProgramData,
-    tf: Vec<Rc<RefCell<Transform>>>,
-}
+    /// Set how many game seconds pass per real second.
+    pub fn set_time_scale(&mut self, time_scale: f32) -> &mut Self {
+        self.state
+            .lock()
+            .unwrap()
+            .get_time_mut()
+            .set_time_scale(time_scale);
+        self
+    }
 
-impl TorusGen {
-    pub fn new(program_data: ProgramData) -> Self {
-        Self {
-            program_data,
-            tf: vec![],
-        }
+    pub fn get_time_scale(&self) -> f32 {
+        self.state.lock().unwrap().get_time().get_time_scale()
     }
-}
 
-impl Steppable<WorldState> for TorusGen {
-    fn start(&mut self, s: &mut WorldState) -> Result<(), StepError<String>> {
-        for _ in 0..50 {
-            let new_obj = WorldObject3DInit {
-                render: Some(RenderComponent {
-                    gl_program_data: self.program_data.clone(),
-                    renderer: s
-                        .get_renderer()
-                        .ok_or_else(|| Fatal("No renderer".into()))?,
-                    material: Material {
-                        color: (1.0, 0.6, 1.0, 1.0),
-                    },
-                }),
-                mesh: Some(MeshComponent {
-                    data: panic!("Not implemented"), //mesh::wavefront_obj::into_vertex_vec("ERROR").expect("Could not convert "),
-                }),
-                ..Default::default()
-            }
-            .init();
-            let tf = new_obj.tf_rc.clone();
-            set_rand_pos(&mut tf.borrow_mut());
-            tf.borrow_mut().set_scale(Vector3::new(1., 1., 1.));
-            self.tf.push(tf);
-            s.add_world_obj(new_obj);
-        }
-        Ok(())
+    pub fn start(&mut self) -> Result<(), String> {
+        // The first frame's delta time counts from here.
+        self.timer.tick();
+        let mut state_mg = self.state.lock().unwrap();
+        let pre_start_cbs = state_mg.scripted_components();
+        state_mg.run_starts(&pre_start_cbs)?;
+        state_mg.apply_commands()
     }
 
-    fn step(&mut self, s: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
-        for t in &mut self.tf {
-            spin_tf(
-                &mut t.borrow_mut(),
-                EulerAngles3D::from_deg(27.0, 73.0, 13.0),
-                s.delta_time,
-            );
+    pub fn step(&mut self) -> Result<(), String> {
+        let mut state_mg = self.state.lock().unwrap();
+        let unscaled_delta = self.timer.tick();
+        if !self.paused {
+            state_mg.get_time_mut().advance(unscaled_delta);
+        } else if std::mem::take(&mut self.step_once) {
+            let delta = self.fixed_timestep.get_fixed_delta_time();
+            state_mg.get_time_mut().advance_by(unscaled_delta, delta);
+        } else {
+            state_mg.get_time_mut().advance_by(unscaled_delta, 0.);
+            // Input keeps arriving while paused, so hand it out rather than
+            // letting it pile up until resuming.
+            state_mg.deliver_events()?;
+            state_mg.apply_commands()?;
+            state_mg.update_world_transforms();
+            state_mg.render();
+            state_mg.inc_frame_count();
+            return Ok(());
         }
-        Ok(())
-    }
-}
 
-#[derive(Debug, Clone)]
-struct MouseFollower {
-    pub(super) tf: Rc<RefCell<Transform>>,
-}
+        let fixed_steps = self
+            .fixed_timestep
+            .accumulate(state_mg.get_time().get_delta_time());
+        state_mg.get_time_mut().set_fixed_step(&self.fixed_timestep);
+        let pre_step_cbs = state_mg.scripted_components();
+        state_mg.run_steps(&self, &pre_step_cbs)?;
+        state_mg.apply_commands()?;
 
-impl Steppable<WorldState> for MouseFollower {
-    fn step(&mut self, state: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
-        let mut tf = self.tf.borrow_mut();
-        match (state.get_inputs(), state.get_canvas()) {
-            (Some(inputs), Some(canvas)) => {
-                let cur_pos = tf.get_position();
-                let ratio = util::canvas_aspect_ratio(&canvas);
-                let x = inputs.get_mouse_view_x() * 1000. * ratio;
-                let y = inputs.get_mouse_view_y() * 1000. / ratio;
-                tf.set_position(Vector3::new(
-                    lerp(cur_pos[0], x, 0.5),
-                    lerp(cur_pos[1], y, 0.5),
-                    0.,
-                ));
-                tf.set_euler_rotation(EulerAngles3D::from_deg(
-                    180.0 * state.get_frame_count() as f32 / 60.0,
-                    x / 2.,
-                    y / 4.,
-                ));
-            }
-            _ => {
-                tf.set_position(Vector3::zeros());
-                tf.set_euler_rotation(EulerAngles3D::zeros());
-            }
+        for _ in 0..fixed_steps {
+            let fixed_step_cbs = state_mg.scripted_components();
+            state_mg.run_fixed_steps(&self, &fixed_step_cbs)?;
+            state_mg.apply_commands()?;
         }
+
+        // Everything published up to here is seen by the end of the frame.
+        state_mg.deliver_events()?;
+
+        let late_step_cbs = state_mg.scripted_components();
+        state_mg.run_late_steps(&self, &late_step_cbs)?;
+        state_mg.apply_commands()?;
+        state_mg.update_world_transforms();
+        state_mg.render();
+
+        state_mg.inc_frame_count();
         Ok(())
     }
+
+    pub fn get_mesh_data(&self, idx: usize) {
+        
+    }
 }
 
 fn get_color() -> Vec<u8> {
@@ -390,3 +541,69 @@ fn get_color() -> Vec<u8> {
     );
     */
 }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::clock::VirtualClock;
+    use crate::steppables::{StepError, Steppable};
+
+    #[derive(Clone, Default)]
+    struct Recorder {
+        deltas: Rc<RefCell<Vec<f32>>>,
+        fixed_steps: Rc<Cell<u32>>,
+    }
+
+    impl Steppable<WorldState> for Recorder {
+        fn step(&mut self, state: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
+            self.deltas
+                .borrow_mut()
+                .push(state.get_time().get_delta_time());
+            Ok(())
+        }
+
+        fn fixed_step(&mut self, _: &mut WorldState) -> Result<(), StepError<String>> {
+            self.fixed_steps.set(self.fixed_steps.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn steps_follow_the_clock() {
+        let clock = VirtualClock::new();
+        let recorder = Recorder::default();
+        let mut game_loop = GameLoop::empty();
+        game_loop
+            .set_clock(clock.clone())
+            .set_fixed_timestep(0.125, 4);
+        game_loop
+            .state
+            .lock()
+            .unwrap()
+            .add_scripted_component(recorder.clone());
+        game_loop.start().unwrap();
+
+        clock.advance(0.25);
+        game_loop.step().unwrap();
+        assert_eq!(recorder.fixed_steps.get(), 2);
+
+        game_loop.set_time_scale(0.5);
+        clock.advance(0.25);
+        game_loop.step().unwrap();
+        assert_eq!(recorder.fixed_steps.get(), 3);
+
+        // Paused frames don't step at all, unless stepping once.
+        game_loop.pause();
+        clock.advance(0.25);
+        game_loop.step().unwrap();
+        assert_eq!(recorder.fixed_steps.get(), 3);
+        game_loop.step_once();
+        clock.advance(0.25);
+        game_loop.step().unwrap();
+        assert_eq!(recorder.fixed_steps.get(), 4);
+
+        assert_eq!(*recorder.deltas.borrow(), vec![0.25, 0.125, 0.125]);
+    }
+}
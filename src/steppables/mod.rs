@@ -1,4 +1,6 @@
+mod object_script;
 mod steppable;
 
+pub use object_script::ObjectScript;
 pub use steppable::StepError;
 pub use steppable::Steppable;
@@ -0,0 +1,46 @@
+use crate::spin::GameLoop;
+use crate::steppables::StepError;
+use crate::world_object::WorldObjectId;
+use crate::world_state::WorldState;
+
+/// Behaviour attached to a single world object, stepped alongside the
+/// global `Steppable`s for as long as the object exists.
+pub trait ObjectScript {
+    /// Name to report errors under.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+    /// Called once, as soon as the owning object is added.
+    fn start(
+        &mut self,
+        _owner: WorldObjectId,
+        _state: &mut WorldState,
+    ) -> Result<(), StepError<String>> {
+        Ok(())
+    }
+    // Step on the main thread at an arbitrary rate.
+    fn step(
+        &mut self,
+        _owner: WorldObjectId,
+        _state: &mut WorldState,
+        _glp: &GameLoop,
+    ) -> Result<(), StepError<String>> {
+        Ok(())
+    }
+    // Step at a fixed rate.
+    fn fixed_step(
+        &mut self,
+        _owner: WorldObjectId,
+        _state: &mut WorldState,
+    ) -> Result<(), StepError<String>> {
+        Ok(())
+    }
+    // Step only after all `step`s are complete.
+    fn late_step(
+        &mut self,
+        _owner: WorldObjectId,
+        _state: &mut WorldState,
+    ) -> Result<(), StepError<String>> {
+        Ok(())
+    }
+}
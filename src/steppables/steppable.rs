@@ -7,9 +7,9 @@ pub enum StepError<T> {
     Ignore,
     /// Exit immediately and stop stepping.
     Fatal(T),
-    /// Exit after stepping through every
+    /// Exit after stepping through every other steppable in the phase.
     FatalPostStep(T),
-    /// Disable this object's future steps, but keep stepping.
+    /// Disable this steppable's future steps, but keep stepping the rest.
     SelfDisable(T),
     /// Continue stepping through, printing out an error.
     Recover(T),
@@ -42,33 +42,12 @@ impl<T: AsRef<str> + std::fmt::Display> StepError<T> {
     }
 }
 
-impl<T: AsRef<str> + Clone> StepError<T> {
-    pub fn store_nonfatal(self, storage: &mut Self) -> Result<(), StepError<T>> {
-        use StepError::*;
-        match &self {
-            Fatal(s) => Err(Fatal(s.clone())),
-            Ignore => {
-                *storage = StepError::Ignore;
-                Ok(())
-            }
-            FatalPostStep(s) => {
-                *storage = FatalPostStep(s.clone());
-                Ok(())
-            }
-            SelfDisable(s) => {
-                *storage = SelfDisable(s.clone());
-                Ok(())
-            }
-            Recover(s) => {
-                *storage = Recover(s.clone());
-                Ok(())
-            }
-        }
-    }
-}
-
 /// An object which can be stepped on the main thread.
 pub trait Steppable<S>: dyn_clone::DynClone {
+    /// Name to report errors under.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
     fn start(&mut self, _state: &mut S) -> Result<(), StepError<String>> {
         Ok(())
     }
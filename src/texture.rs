@@ -0,0 +1,109 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlImageElement, WebGl2RenderingContext as Gl, WebGlTexture};
+
+use crate::util::Rfc;
+
+/// Sampler uniform for a material's diffuse texture.
+pub const DIFFUSE_TEXTURE_UNIFORM: &str = "u_diffuseTexture";
+
+/// An image uploaded to the GPU, ready to be bound to a sampler.
+#[derive(Debug)]
+pub struct Texture {
+    webgl_texture: WebGlTexture,
+}
+
+impl Texture {
+    /// Upload a fully loaded image, generating mipmaps for it.
+    pub fn from_image(ctx: &Gl, image: &HtmlImageElement) -> Result<Self, String> {
+        let webgl_texture = ctx.create_texture().ok_or("Unable to create texture")?;
+        ctx.bind_texture(Gl::TEXTURE_2D, Some(&webgl_texture));
+        // Images start at the top, UVs start at the bottom.
+        ctx.pixel_storei(Gl::UNPACK_FLIP_Y_WEBGL, 1);
+        let uploaded = ctx.tex_image_2d_with_u32_and_u32_and_html_image_element(
+            Gl::TEXTURE_2D,
+            0,
+            Gl::RGBA as i32,
+            Gl::RGBA,
+            Gl::UNSIGNED_BYTE,
+            image,
+        );
+        ctx.pixel_storei(Gl::UNPACK_FLIP_Y_WEBGL, 0);
+        uploaded.map_err(|e| {
+            e.as_string()
+                .unwrap_or_else(|| format!("Unable to upload image {}", image.src()))
+        })?;
+        ctx.generate_mipmap(Gl::TEXTURE_2D);
+        ctx.tex_parameteri(
+            Gl::TEXTURE_2D,
+            Gl::TEXTURE_MIN_FILTER,
+            Gl::LINEAR_MIPMAP_LINEAR as i32,
+        );
+        ctx.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_MAG_FILTER, Gl::LINEAR as i32);
+        ctx.bind_texture(Gl::TEXTURE_2D, None);
+        Ok(Self { webgl_texture })
+    }
+
+    /// Upload raw RGBA8 pixels, without mipmaps.
+    pub fn from_rgba(ctx: &Gl, width: u32, height: u32, pixels: &[u8]) -> Result<Self, String> {
+        let webgl_texture = ctx.create_texture().ok_or("Unable to create texture")?;
+        ctx.bind_texture(Gl::TEXTURE_2D, Some(&webgl_texture));
+        ctx.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            Gl::TEXTURE_2D,
+            0,
+            Gl::RGBA as i32,
+            width as i32,
+            height as i32,
+            0,
+            Gl::RGBA,
+            Gl::UNSIGNED_BYTE,
+            Some(pixels),
+        )
+        .map_err(|e| {
+            e.as_string()
+                .unwrap_or_else(|| "Unable to upload pixel data".into())
+        })?;
+        ctx.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_MIN_FILTER, Gl::NEAREST as i32);
+        ctx.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_MAG_FILTER, Gl::NEAREST as i32);
+        ctx.bind_texture(Gl::TEXTURE_2D, None);
+        Ok(Self { webgl_texture })
+    }
+
+    /// A 1x1 opaque white texture, which leaves colours unchanged when
+    /// sampled and multiplied in.
+    pub fn white(ctx: &Gl) -> Result<Self, String> {
+        Self::from_rgba(ctx, 1, 1, &[255, 255, 255, 255])
+    }
+
+    /// Bind this texture to the given texture unit.
+    pub fn bind(&self, ctx: &Gl, unit: u32) {
+        ctx.active_texture(Gl::TEXTURE0 + unit);
+        ctx.bind_texture(Gl::TEXTURE_2D, Some(&self.webgl_texture));
+    }
+}
+
+/// Filled once an image has finished decoding, or has failed to.
+pub type ImageCell = Rfc<Option<Result<HtmlImageElement, String>>>;
+
+/// Start loading an image in the background.
+pub fn get_image_from_url(url: String) -> ImageCell {
+    let out = Rc::new(RefCell::new(None));
+    let out_clone = out.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        *out_clone.borrow_mut() = Some(async_get_image_from_url(&url).await);
+    });
+    out
+}
+
+pub async fn async_get_image_from_url(url: &str) -> Result<HtmlImageElement, String> {
+    let image =
+        HtmlImageElement::new().map_err(|_| "Unable to create image element".to_string())?;
+    image.set_cross_origin(Some("anonymous"));
+    image.set_src(url);
+    JsFuture::from(image.decode())
+        .await
+        .map_err(|_| format!("Unable to load image {}", url))?;
+    Ok(image)
+}
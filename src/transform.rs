@@ -1,49 +1,83 @@
 use crate::maths_utils::*;
-use na::{Matrix3, Matrix4, Rotation3, Vector2, Vector3, Vector4};
-use std::cell::RefCell;
+use na::{Matrix3, Matrix4, Unit, UnitQuaternion, Vector2, Vector3};
+use std::cell::Cell;
 
 #[derive(Debug, Clone)]
 pub struct Transform {
     translation: Vector3<f32>,
-    rotation: Vector3<f32>,
+    rotation: UnitQuaternion<f32>,
     scale: Vector3<f32>,
-    cached_scaling: Matrix4<f32>,
+    /// Local matrix, rebuilt on first use after a change.
+    cached_local: Cell<Option<Matrix4<f32>>>,
+    /// World matrix, rebuilt on first use after a change here or in the
+    /// parent.
+    cached_world: Cell<Option<Matrix4<f32>>>,
+    /// World matrix of the owning object's parent, if it has one.
+    parent_world: Option<Matrix4<f32>>,
+    /// Set whenever the local transform changes, until taken by the
+    /// hierarchy update.
+    changed: bool,
 }
 
 impl Transform {
     pub fn identity() -> Self {
         Transform {
             translation: Vector3::zeros(),
-            rotation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
             scale: Vector3::new(1., 1., 1.),
-            cached_scaling: Matrix4::identity(),
+            cached_local: Cell::new(None),
+            cached_world: Cell::new(None),
+            parent_world: None,
+            changed: true,
         }
     }
 
-    pub fn new(translation: Vector3<f32>, rotation: Vector3<f32>, scale: Vector3<f32>) -> Self {
+    pub fn new(
+        translation: Vector3<f32>,
+        rotation: UnitQuaternion<f32>,
+        scale: Vector3<f32>,
+    ) -> Self {
         Transform {
             translation,
             rotation,
             scale,
-            cached_scaling: Matrix4::identity(),
+            cached_local: Cell::new(None),
+            cached_world: Cell::new(None),
+            parent_world: None,
+            changed: true,
         }
     }
 
     pub fn set_position(&mut self, position: Vector3<f32>) -> &mut Self {
         self.translation = position;
+        self.invalidate();
         self
     }
     pub fn get_position(&self) -> Vector3<f32> {
         self.translation
     }
 
-    /// Set rotation in Euler coordinates (pitch, roll, yaw) in radians.
+    /// An identity transform rotated by `angle` radians around `axis`.
+    pub fn from_axis_angle(axis: &Unit<Vector3<f32>>, angle: f32) -> Self {
+        let mut tf = Self::identity();
+        tf.set_rotation(UnitQuaternion::from_axis_angle(axis, angle));
+        tf
+    }
+
+    /// An identity transform rotated around `axis_angle`, by its length in
+    /// radians.
+    pub fn from_scaled_axis(axis_angle: Vector3<f32>) -> Self {
+        let mut tf = Self::identity();
+        tf.set_rotation(UnitQuaternion::from_scaled_axis(axis_angle));
+        tf
+    }
+
+    /// Set rotation in Euler coordinates (roll, pitch, yaw) in radians.
     pub fn set_euler_rotation_raw(&mut self, rot: Vector3<f32>) -> &mut Self {
-        self.rotation = rot;
-        self
+        self.set_rotation(UnitQuaternion::from_euler_angles(rot[0], rot[1], rot[2]))
     }
 
-    /// Set rotation in Euler coordinates (pitch, roll, yaw) in radians.
+    /// Set rotation in Euler coordinates (roll, pitch, yaw).
     pub fn set_euler_rotation(&mut self, rot: EulerAngles3D<f32>) -> &mut Self {
         self.set_euler_rotation_raw(Vector3::new(
             rot.roll.as_rad(),
@@ -51,17 +85,41 @@ impl Transform {
             rot.yaw.as_rad(),
         ))
     }
+
+    /// Return the rotation as Euler angles. These may differ from the ones
+    /// set, but describe the same rotation.
     pub fn get_euler_rotation(&self) -> EulerAngles3D<f32> {
-        EulerAngles3D::from_rad(self.rotation[0], self.rotation[1], self.rotation[2])
+        let (roll, pitch, yaw) = self.rotation.euler_angles();
+        EulerAngles3D::from_rad(roll, pitch, yaw)
     }
 
-    pub fn set_rotation(&mut self, rot: Vector4<f32>) -> &mut Self {
-        panic!();
+    pub fn set_rotation(&mut self, rot: UnitQuaternion<f32>) -> &mut Self {
+        self.rotation = rot;
+        self.invalidate();
+        self
+    }
+
+    pub fn get_rotation(&self) -> UnitQuaternion<f32> {
+        self.rotation
+    }
+
+    /// Apply `rot` on top of the current rotation, in the parent's space.
+    pub fn rotate_by(&mut self, rot: UnitQuaternion<f32>) -> &mut Self {
+        self.set_rotation(rot * self.rotation)
+    }
+
+    /// Rotate to face `target` along local -Z, keeping local +Y as close
+    /// to `up` as possible. Both are in the parent's space.
+    pub fn look_at(&mut self, target: Vector3<f32>, up: Vector3<f32>) -> &mut Self {
+        self.set_rotation(UnitQuaternion::face_towards(
+            &(self.translation - target),
+            &up,
+        ))
     }
 
     pub fn set_scale(&mut self, scale: Vector3<f32>) -> &mut Self {
         self.scale = scale;
-        self.cached_scaling = Matrix4::new_nonuniform_scaling(&scale);
+        self.invalidate();
         self
     }
 
@@ -78,25 +136,146 @@ impl Transform {
         self.to_mat3().iter().copied().collect()
     }
 
+    /// Drop the cached matrices and flag the change for the hierarchy.
+    fn invalidate(&mut self) {
+        self.cached_local.set(None);
+        self.cached_world.set(None);
+        self.changed = true;
+    }
+
     pub fn to_mat4(&self) -> na::Matrix4<f32> {
-        let mut out = self.cached_scaling
-            * Rotation3::from_euler_angles(self.rotation[0], self.rotation[1], self.rotation[2])
-                .to_homogeneous();
-        out.append_translation_mut(&self.translation);
-        out
+        if let Some(local) = self.cached_local.get() {
+            return local;
+        }
+        let mut local =
+            Matrix4::new_nonuniform_scaling(&self.scale) * self.rotation.to_homogeneous();
+        local.append_translation_mut(&self.translation);
+        self.cached_local.set(Some(local));
+        local
     }
 
     pub fn to_mat4_into<'a>(&self, out: &'a mut na::Matrix4<f32>) -> &'a mut na::Matrix4<f32> {
-        self.cached_scaling.mul_to(
-            &Rotation3::from_euler_angles(self.rotation[0], self.rotation[1], self.rotation[2])
-                .to_homogeneous(),
-            out,
-        );
-        out.append_translation_mut(&self.translation);
+        *out = self.to_mat4();
         out
     }
 
     pub fn to_mat4_vec(&self) -> Vec<f32> {
         self.to_mat4().iter().copied().collect()
     }
+
+    /// Return the matrix taking local space to world space, composing the
+    /// parent's world matrix with this transform.
+    pub fn world_mat(&self) -> Matrix4<f32> {
+        if let Some(world) = self.cached_world.get() {
+            return world;
+        }
+        let world = match &self.parent_world {
+            Some(parent) => parent * self.to_mat4(),
+            None => self.to_mat4(),
+        };
+        self.cached_world.set(Some(world));
+        world
+    }
+
+    pub fn world_mat_into<'a>(&self, out: &'a mut Matrix4<f32>) -> &'a mut Matrix4<f32> {
+        *out = self.world_mat();
+        out
+    }
+
+    pub fn get_world_position(&self) -> Vector3<f32> {
+        match &self.parent_world {
+            Some(parent) => parent.transform_point(&self.translation.into()).coords,
+            None => self.translation,
+        }
+    }
+
+    /// Move the transform so it sits at `position` in world space.
+    pub fn set_world_position(&mut self, position: Vector3<f32>) -> &mut Self {
+        let local = match self.parent_world.and_then(|p| p.try_inverse()) {
+            Some(inv_parent) => inv_parent.transform_point(&position.into()).coords,
+            None => position,
+        };
+        self.set_position(local)
+    }
+
+    pub fn get_parent_world(&self) -> Option<&Matrix4<f32>> {
+        self.parent_world.as_ref()
+    }
+
+    /// Set the world matrix this transform is relative to. Called by the
+    /// hierarchy update when the parent moves.
+    pub fn set_parent_world(&mut self, parent_world: Option<Matrix4<f32>>) -> &mut Self {
+        self.parent_world = parent_world;
+        self.cached_world.set(None);
+        self
+    }
+
+    /// Flag the transform as moved, so the next hierarchy update
+    /// recomputes it and its descendants.
+    pub fn mark_changed(&mut self) -> &mut Self {
+        self.changed = true;
+        self
+    }
+
+    /// Return whether the transform moved since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::replace(&mut self.changed, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_position_follows_parent() {
+        let mut parent = Transform::identity();
+        parent
+            .set_position(Vector3::new(1., 2., 3.))
+            .set_scale(Vector3::new(2., 2., 2.));
+        let mut child = Transform::identity();
+        child
+            .set_position(Vector3::new(1., 0., 0.))
+            .set_parent_world(Some(parent.world_mat()));
+        assert_eq!(child.get_world_position(), Vector3::new(3., 2., 3.));
+
+        child.set_world_position(Vector3::new(1., 2., 5.));
+        assert_eq!(child.get_position(), Vector3::new(0., 0., 1.));
+        assert_eq!(child.get_world_position(), Vector3::new(1., 2., 5.));
+    }
+
+    #[test]
+    fn cached_matrices_follow_changes() {
+        let mut tf = Transform::identity();
+        tf.set_position(Vector3::new(1., 0., 0.));
+        assert_eq!(tf.world_mat().column(3)[0], 1.);
+        tf.set_scale(Vector3::new(2., 2., 2.));
+        assert_eq!(tf.to_mat4()[(0, 0)], 2.);
+        tf.set_parent_world(Some(Matrix4::new_translation(&Vector3::new(0., 5., 0.))));
+        assert_eq!(tf.get_world_position(), Vector3::new(1., 5., 0.));
+        assert_eq!(tf.world_mat().column(3)[1], 5.);
+    }
+
+    #[test]
+    fn euler_rotation_round_trips() {
+        let mut tf = Transform::identity();
+        tf.set_euler_rotation(EulerAngles3D::from_rad(0.3, -0.2, 1.1));
+        let rot = tf.get_euler_rotation();
+        assert!((rot.roll.as_rad() - 0.3).abs() < 1e-5);
+        assert!((rot.pitch.as_rad() + 0.2).abs() < 1e-5);
+        assert!((rot.yaw.as_rad() - 1.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn look_at_faces_down_negative_z() {
+        let mut tf = Transform::identity();
+        tf.set_position(Vector3::new(1., 1., 1.))
+            .look_at(Vector3::new(1., 1., -4.), Vector3::y());
+        let forward = tf.get_rotation() * -Vector3::z();
+        assert!((forward - -Vector3::z()).norm() < 1e-5);
+
+        tf.look_at(Vector3::new(6., 1., 1.), Vector3::y());
+        let forward = tf.get_rotation() * -Vector3::z();
+        assert!((forward - Vector3::x()).norm() < 1e-5);
+    }
 }
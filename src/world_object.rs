@@ -1,13 +1,21 @@
 use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::rc::{Rc, Weak};
 use std::sync::Arc;
 
+use na::{Matrix4, Vector3};
+
 use web_sys::WebGl2RenderingContext as Gl;
 
+use crate::camera::Camera;
+use crate::mesh::IndexedMesh;
 use crate::rendering::Renderer;
 use crate::rendering::*;
-use crate::steppables::Steppable;
+use crate::scene::MeshRef;
+use crate::steppables::ObjectScript;
+use crate::texture::{Texture, DIFFUSE_TEXTURE_UNIFORM};
 use crate::transform::Transform;
+use crate::util::Rfc;
 use crate::world_state::WorldState;
 
 #[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash)]
@@ -15,10 +23,88 @@ pub struct WorldObjectId(pub usize);
 
 pub struct MeshComponent {
     pub data: Vec<f32>,
+    /// Optional triangle indices in to `data`.
+    pub indices: Option<Vec<u32>>,
+    /// Optional xyz normals, one per vertex in `data`.
+    pub normals: Option<Vec<f32>>,
+    /// Optional uv texture coordinates, one pair per vertex in `data`.
+    pub texcoords: Option<Vec<f32>>,
+    /// Primitive to draw, such as `TRIANGLES` or `LINES`.
+    pub draw_type: u32,
+}
+
+impl MeshComponent {
+    /// Copy an indexed mesh, to be drawn as triangles.
+    pub fn from_indexed(mesh: &IndexedMesh) -> Self {
+        Self {
+            data: mesh.positions.clone(),
+            indices: Some(mesh.indices.clone()),
+            normals: Some(mesh.normals.clone()),
+            texcoords: Some(mesh.texcoords.clone()).filter(|t| !t.is_empty()),
+            draw_type: Gl::TRIANGLES,
+        }
+    }
+}
+
+impl Default for MeshComponent {
+    fn default() -> Self {
+        Self {
+            data: vec![],
+            indices: None,
+            normals: None,
+            texcoords: None,
+            draw_type: Gl::TRIANGLES,
+        }
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct Material {
+    /// Diffuse colour, with opacity as alpha.
     pub color: (f32, f32, f32, f32),
+    pub ambient: (f32, f32, f32),
+    pub specular: (f32, f32, f32),
+    /// Specular exponent. Higher values give smaller, sharper highlights.
+    pub shininess: f32,
+    /// Multiplied with `color` by programs with a diffuse sampler.
+    pub diffuse_texture: Option<Rc<Texture>>,
+}
+
+impl Material {
+    /// The values lit programs read as uniforms.
+    pub fn uniforms(&self) -> MaterialUniforms {
+        MaterialUniforms {
+            ambient: self.ambient,
+            specular: self.specular,
+            shininess: self.shininess,
+            opacity: self.color.3,
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            color: (1., 1., 1., 1.),
+            ambient: (1., 1., 1.),
+            specular: (0., 0., 0.),
+            shininess: 0.,
+            diffuse_texture: None,
+        }
+    }
+}
+
+/// Draw an object as one instance of a mesh shared with other objects.
+pub struct InstancedComponent {
+    pub item: Rc<InstancedRenderItem>,
+    pub material: Material,
+}
+
+/// An object's membership of an `InstancedRenderItem`.
+#[derive(Debug, Clone)]
+pub struct InstanceHandle {
+    pub item: Rc<InstancedRenderItem>,
+    pub key: InstanceKey,
 }
 
 pub struct RenderComponent {
@@ -28,11 +114,17 @@ pub struct RenderComponent {
 }
 
 pub struct WorldObject3DInit {
+    pub name: String,
+    pub tags: BTreeSet<String>,
     pub tf: Transform,
     pub mesh: Option<MeshComponent>,
     pub render: Option<RenderComponent>,
     pub render_item: Option<RenderItem>,
-    pub scripts: Vec<Rc<dyn Steppable<WorldState>>>,
+    pub instanced: Option<InstancedComponent>,
+    /// Where `mesh` came from, so the object can be saved in a scene.
+    pub mesh_ref: Option<MeshRef>,
+    /// Started when the object is added, and stepped until it's removed.
+    pub scripts: Vec<Rfc<dyn ObjectScript>>,
     pub children_ids: Vec<WorldObjectId>,
     pub parent_id: Option<WorldObjectId>,
     #[doc(hidden)]
@@ -53,7 +145,7 @@ impl WorldObject3DInit {
                         color_data.push(rend.material.color.2);
                         color_data.push(rend.material.color.3);
                     }
-                    let buffer_info = BufferInfo::new(ctx.clone())
+                    let mut buffer_info = BufferInfo::new(ctx.clone())
                         .add_buffer(
                             "a_position".into(),
                             m.data,
@@ -64,12 +156,43 @@ impl WorldObject3DInit {
                             color_data,
                             BufferSettings::new(4, Gl::FLOAT),
                         );
+                    if let Some(normals) = m.normals {
+                        // Unlit programs have nowhere to put normals.
+                        if rend.gl_program_data.has_attribute("a_normal") {
+                            buffer_info = buffer_info.add_buffer(
+                                "a_normal".into(),
+                                normals,
+                                BufferSettings::new(3, Gl::FLOAT),
+                            );
+                        }
+                    }
+                    if let Some(texcoords) = m.texcoords {
+                        if rend.gl_program_data.has_attribute("a_texcoord") {
+                            buffer_info = buffer_info.add_buffer(
+                                "a_texcoord".into(),
+                                texcoords,
+                                BufferSettings::new(2, Gl::FLOAT),
+                            );
+                        }
+                    }
+                    if let Some(indices) = m.indices {
+                        buffer_info = buffer_info.set_indices(indices);
+                    }
+                    let material = rend.material.uniforms();
+                    let textures = rend
+                        .material
+                        .diffuse_texture
+                        .map(|tex| vec![(DIFFUSE_TEXTURE_UNIFORM.to_owned(), tex)])
+                        .unwrap_or_default();
                     let vao = ctx.create_vertex_array();
                     Some(Rc::new(
                         RenderItem::builder()
                             .buffer_info(buffer_info)
+                            .textures(textures)
+                            .material(material)
                             .program_data(rend.gl_program_data)
                             .tf(tf_rc.clone())
+                            .draw_type(m.draw_type)
                             .vao(vao.unwrap())
                             .build()
                             .unwrap(),
@@ -78,19 +201,34 @@ impl WorldObject3DInit {
                 (None, None, Some(render_item)) => Some(Rc::new(render_item)),
                 _ => None,
             },
+            instance: self.instanced.map(|inst| InstanceHandle {
+                key: inst.item.add_instance(tf_rc.clone(), inst.material.color),
+                item: inst.item,
+            }),
             self_id: None,
+            camera: None,
             tf_rc,
             children: self.children_ids,
-            parent: None,
-            name: "".to_string(),
+            parent: self.parent_id,
+            scripts: self.scripts,
+            mesh_ref: self.mesh_ref,
+            name: self.name,
+            tags: self.tags,
         };
         obj
     }
 }
 
+/// Whether two handles point at the same script, ignoring vtables.
+pub(super) fn same_script(a: &Rfc<dyn ObjectScript>, b: &Rfc<dyn ObjectScript>) -> bool {
+    Rc::as_ptr(a) as *const () == Rc::as_ptr(b) as *const ()
+}
+
 impl Default for WorldObject3DInit {
     fn default() -> Self {
         Self {
+            name: "".to_string(),
+            tags: BTreeSet::new(),
             tf: Transform::identity(),
             render: None,
             mesh: None,
@@ -98,6 +236,8 @@ impl Default for WorldObject3DInit {
             children_ids: vec![],
             parent_id: None,
             render_item: None,
+            instanced: None,
+            mesh_ref: None,
             __non_exh: (),
         }
     }
@@ -106,10 +246,15 @@ impl Default for WorldObject3DInit {
 pub struct WorldObject3D {
     pub tf_rc: Rc<RefCell<Transform>>,
     pub render_item: Option<Rc<RenderItem>>,
-    pub name: String,
+    pub instance: Option<InstanceHandle>,
+    pub camera: Option<Rfc<Camera>>,
+    name: String,
+    tags: BTreeSet<String>,
+    pub mesh_ref: Option<MeshRef>,
     pub(super) self_id: Option<WorldObjectId>,
     children: Vec<WorldObjectId>,
     parent: Option<WorldObjectId>,
+    scripts: Vec<Rfc<dyn ObjectScript>>,
 }
 
 impl WorldObject3D {
@@ -127,11 +272,103 @@ impl WorldObject3D {
         }
     }
 
+    pub fn get_parent_id(&self) -> Option<WorldObjectId> {
+        self.parent
+    }
+
+    /// Set the parent id alone. Prefer `WorldState::set_parent`, which
+    /// also keeps the parents' children lists in step.
     pub fn set_parent_id(&mut self, parent: Option<WorldObjectId>) -> &mut Self {
         self.parent = parent;
+        // The world matrix is relative to a different parent now.
+        self.tf_rc.borrow_mut().mark_changed();
         self
     }
 
+    pub fn get_scripts(&self) -> &[Rfc<dyn ObjectScript>] {
+        &self.scripts
+    }
+
+    pub(super) fn add_script(&mut self, script: Rfc<dyn ObjectScript>) {
+        self.scripts.push(script);
+    }
+
+    pub(super) fn remove_script(&mut self, script: &Rfc<dyn ObjectScript>) {
+        self.scripts.retain(|s| !same_script(s, script));
+    }
+
+    pub(super) fn add_child_id(&mut self, child: WorldObjectId) {
+        if !self.children.contains(&child) {
+            self.children.push(child);
+        }
+    }
+
+    pub(super) fn remove_child_id(&mut self, child: WorldObjectId) {
+        self.children.retain(|c| *c != child);
+    }
+
+    /// Return a copy of the transform relative to the parent.
+    pub fn get_local_tf(&self) -> Transform {
+        self.tf_rc.borrow().clone()
+    }
+
+    /// Replace the transform relative to the parent.
+    pub fn set_local_tf(&mut self, tf: Transform) -> &mut Self {
+        {
+            let mut own = self.tf_rc.borrow_mut();
+            let parent_world = own.get_parent_world().copied();
+            *own = tf;
+            own.set_parent_world(parent_world).mark_changed();
+        }
+        self
+    }
+
+    pub fn get_local_position(&self) -> Vector3<f32> {
+        self.tf_rc.borrow().get_position()
+    }
+
+    pub fn set_local_position(&mut self, position: Vector3<f32>) -> &mut Self {
+        self.tf_rc.borrow_mut().set_position(position);
+        self
+    }
+
+    /// Return the matrix taking this object's local space to world space,
+    /// as of the last `WorldState::update_world_transforms`.
+    pub fn get_world_mat(&self) -> Matrix4<f32> {
+        self.tf_rc.borrow().world_mat()
+    }
+
+    pub fn get_world_position(&self) -> Vector3<f32> {
+        self.tf_rc.borrow().get_world_position()
+    }
+
+    pub fn set_world_position(&mut self, position: Vector3<f32>) -> &mut Self {
+        self.tf_rc.borrow_mut().set_world_position(position);
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Rename without updating the lookup index. Use
+    /// `WorldState::rename_world_obj` for objects already added.
+    pub(super) fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn get_tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub(super) fn tags_mut(&mut self) -> &mut BTreeSet<String> {
+        &mut self.tags
+    }
+
     pub fn get_child_id_by_name(&self, state: &WorldState, name: &str) -> Option<WorldObjectId> {
         for id in self.children.iter() {
             let is_obj = state
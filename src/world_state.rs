@@ -1,51 +1,190 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::rc;
 use std::rc::Rc;
 use std::sync::Arc;
 
+use na::Matrix4;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use slab::Slab;
+use web_sys::WebGl2RenderingContext as Gl;
 
+use crate::camera::Camera;
+use crate::clock::Time;
+use crate::commands::WorldCommand;
+use crate::component::{Components, Query};
+use crate::events::{EventBus, ObjectDestroyed, ObjectSpawned, SubscriptionId};
 use crate::inputs;
+use crate::js_bindings;
+use crate::lighting::Lighting;
+use crate::mesh::wavefront_obj::material_library_url;
+use crate::mesh::{IndexedMesh, MeshGroup, MeshSet};
+use crate::object_index::ObjectIndex;
+use crate::prefab::Prefab;
 use crate::rendering::*;
+use crate::scene::{
+    MeshLayout, MeshRef, ParsedMeshes, SceneAssets, SceneData, SceneObject, TransformData,
+};
 use crate::spin::GameLoop;
-use crate::steppables::Steppable;
+use crate::steppables::{ObjectScript, StepError, Steppable};
+use crate::transform::Transform;
 use crate::util::{Rfc, Wfc};
-use crate::world_object::{WorldObject3D, WorldObject3DInit, WorldObjectId};
+use crate::world_object::{
+    same_script, InstancedComponent, Material, MeshComponent, RenderComponent, WorldObject3D,
+    WorldObject3DInit, WorldObjectId,
+};
 
 pub enum RendQueueType {
     Fwd,
     Rev,
 }
 
+/// What happens to the children of a removed world object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildPolicy {
+    /// Remove the whole subtree.
+    Remove,
+    /// Hand the children to the removed object's parent, keeping their
+    /// local transforms.
+    Reparent,
+}
+
 /// Type alias so we can change the provided type all together.
 type InputT = inputs::InputBinding;
 type CanvasT = web_sys::HtmlCanvasElement;
 
 type WorldSteppable = dyn Steppable<WorldState>;
 
-pub struct WorldState {
-    pub delta_time: f32,
+/// Identifies a global steppable, for removing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SteppableId(usize);
+
+/// Steps a script on behalf of the object owning it, so object scripts
+/// run through the same lists as global steppables.
+#[derive(Clone)]
+struct OwnedScript {
+    owner: WorldObjectId,
+    script: Rfc<dyn ObjectScript>,
+}
+
+impl OwnedScript {
+    fn describe(&self) -> String {
+        format!("{} on {:?}", self.script.borrow().name(), self.owner)
+    }
+
+    /// False once the owner has been removed, even if its id was reused.
+    fn is_owned(&self, state: &WorldState) -> bool {
+        state.get_world_obj(&self.owner).is_some_and(|obj| {
+            obj.get_scripts()
+                .iter()
+                .any(|s| same_script(s, &self.script))
+        })
+    }
+}
+
+/// A steppable to run, knowing where it came from so it can be disabled.
+#[derive(Clone)]
+pub struct StepEntry {
+    source: StepSource,
+    steppable: Rfc<WorldSteppable>,
+}
+
+#[derive(Clone)]
+enum StepSource {
+    Global(SteppableId),
+    Script(OwnedScript),
+    Subscriber(SubscriptionId),
+}
+
+impl StepEntry {
+    /// Name of the steppable, to report errors under.
+    fn describe(&self) -> String {
+        match &self.source {
+            StepSource::Global(_) => self.steppable.borrow().name().to_owned(),
+            StepSource::Script(owned) => owned.describe(),
+            StepSource::Subscriber(id) => format!("{:?}", id),
+        }
+    }
+}
+
+impl Steppable<WorldState> for OwnedScript {
+    // Object scripts are started by `WorldState::add_world_obj`.
+
+    fn step(&mut self, state: &mut WorldState, glp: &GameLoop) -> Result<(), StepError<String>> {
+        if !self.is_owned(state) {
+            return Ok(());
+        }
+        self.script.borrow_mut().step(self.owner, state, glp)
+    }
+
+    fn fixed_step(&mut self, state: &mut WorldState) -> Result<(), StepError<String>> {
+        if !self.is_owned(state) {
+            return Ok(());
+        }
+        self.script.borrow_mut().fixed_step(self.owner, state)
+    }
+
+    fn late_step(&mut self, state: &mut WorldState) -> Result<(), StepError<String>> {
+        if !self.is_owned(state) {
+            return Ok(());
+        }
+        self.script.borrow_mut().late_step(self.owner, state)
+    }
+}
 
+pub struct WorldState {
     canvas: Option<Arc<CanvasT>>,
     frame_count: u64,
+    time: Time,
     world_objs: Slab<WorldObject3D>,
-    scripted_components: Vec<Rfc<WorldSteppable>>,
+    index: ObjectIndex,
+    components: Components,
+    events: EventBus<WorldState>,
+    scripted_components: Vec<(SteppableId, Rfc<WorldSteppable>)>,
+    /// What `scripted_components` returns, until a steppable or object
+    /// script is added or removed.
+    step_entries: RefCell<Option<Rc<[StepEntry]>>>,
+    /// Fatal errors from starting object scripts, for `apply_commands`.
+    start_errors: Vec<String>,
+    next_steppable_id: usize,
+    commands: Vec<WorldCommand>,
     inputs: Option<InputT>,
     renderables: RenderableQueues,
     renderer: Option<Arc<dyn Renderer>>,
+    main_camera: Option<WorldObjectId>,
+    lighting: Lighting,
+    prefabs: BTreeMap<String, Prefab>,
+    /// Meshes of instanced prefabs, by prefab name, made by their first
+    /// instance.
+    prefab_items: BTreeMap<String, Rc<InstancedRenderItem>>,
+    /// Meshes of objects loaded from scenes with an `Instanced` layout.
+    scene_items: BTreeMap<MeshRef, Rc<InstancedRenderItem>>,
 }
 
 impl WorldState {
     pub fn new() -> Self {
         WorldState {
             frame_count: 0,
+            time: Time::default(),
             inputs: None,
             renderables: RenderableQueues::new(),
             world_objs: Default::default(),
+            index: ObjectIndex::new(),
+            components: Components::new(),
+            events: EventBus::new(),
             scripted_components: vec![],
+            step_entries: RefCell::new(None),
+            start_errors: vec![],
+            next_steppable_id: 0,
+            commands: vec![],
             renderer: None,
+            main_camera: None,
+            lighting: Lighting::default(),
+            prefabs: BTreeMap::new(),
+            prefab_items: BTreeMap::new(),
+            scene_items: BTreeMap::new(),
             canvas: None,
-            delta_time: 1.0 / 60.0,
         }
     }
 
@@ -71,6 +210,14 @@ impl WorldState {
         self.frame_count
     }
 
+    pub fn get_time(&self) -> &Time {
+        &self.time
+    }
+
+    pub fn get_time_mut(&mut self) -> &mut Time {
+        &mut self.time
+    }
+
     pub fn get_inputs(&self) -> &Option<InputT> {
         &self.inputs
     }
@@ -84,6 +231,21 @@ impl WorldState {
         self.frame_count
     }
 
+    pub fn get_lighting(&self) -> &Lighting {
+        &self.lighting
+    }
+
+    pub fn get_lighting_mut(&mut self) -> &mut Lighting {
+        &mut self.lighting
+    }
+
+    /// Draw every queued item with the current lighting.
+    pub fn render(&mut self) {
+        if let Some(r) = &self.renderer {
+            r.render_all(&mut self.renderables, &self.lighting);
+        }
+    }
+
     pub fn get_rendqueue(&self) -> &RenderableQueues {
         &self.renderables
     }
@@ -103,6 +265,14 @@ impl WorldState {
         }
     }
 
+    /// Queue a shared mesh for instanced drawing. World objects join it
+    /// through `WorldObject3DInit::instanced`.
+    pub fn add_instanced_item(&mut self, item: InstancedRenderItem) -> Rc<InstancedRenderItem> {
+        let item = Rc::new(item);
+        self.renderables.push_instanced_queue(item.clone());
+        item
+    }
+
     pub fn get_world_obj_mut(&mut self, id: &WorldObjectId) -> Option<&mut WorldObject3D> {
         self.world_objs.get_mut(id.0)
     }
@@ -116,62 +286,1541 @@ impl WorldState {
             ..Default::default()
         }
         .init();
-        WorldObjectId(self.world_objs.insert(obj))
+        self.add_world_obj(obj)
     }
 
-    pub fn add_world_obj(&mut self, obj: WorldObject3D) -> WorldObjectId {
+    /// Add an object, linking it to its parent and taking its children
+    /// from wherever they were. Links that would be invalid are dropped.
+    pub fn add_world_obj(&mut self, mut obj: WorldObject3D) -> WorldObjectId {
         if let Some(item) = obj.render_item.clone() {
             self.push_rendqueue(RendQueueType::Fwd, item)
         }
         let children = obj.get_children_ids().to_owned();
+        let parent = obj.get_parent_id();
+        for c in &children {
+            obj.remove_child_id(*c);
+        }
+        obj.set_parent_id(None);
+        if !obj.get_scripts().is_empty() {
+            self.invalidate_step_entries();
+        }
         let new_id = WorldObjectId(self.world_objs.insert(obj));
+        {
+            let obj = &self.world_objs[new_id.0];
+            self.index.insert(new_id, obj.get_name(), obj.get_tags());
+        }
+        if parent.is_some() {
+            self.set_parent(new_id, parent).ok();
+        }
         for c in children {
-            if let Some(child) = self.get_world_obj_mut(&c) {
-                child.set_parent_id(Some(new_id.clone()));
-            }
+            self.set_parent(c, Some(new_id)).ok();
         }
         self.get_world_obj_mut(&new_id).unwrap().self_id = Some(new_id);
+        self.events.publish(ObjectSpawned(new_id));
+        self.start_scripts(new_id);
         new_id
     }
 
-    pub fn scripted_components(&self) -> Vec<Rc<RefCell<WorldSteppable>>> {
-        self.scripted_components.clone()
+    /// Start every script of a newly added object.
+    fn start_scripts(&mut self, id: WorldObjectId) {
+        let scripts = match self.get_world_obj(&id) {
+            Some(obj) => obj.get_scripts().to_vec(),
+            None => return,
+        };
+        for script in scripts {
+            self.start_script(id, &script);
+        }
+    }
+
+    /// Start a script already attached to an object. Errors are handled
+    /// as in a step phase, except fatal ones wait to be returned by the
+    /// next `apply_commands`.
+    fn start_script(&mut self, id: WorldObjectId, script: &Rfc<dyn ObjectScript>) {
+        let started = script.borrow_mut().start(id, self);
+        if let Err(e) = started {
+            let owned = OwnedScript {
+                owner: id,
+                script: script.clone(),
+            };
+            let name = owned.describe();
+            let mut errors = vec![];
+            if let Err(fatal) =
+                self.handle_step_error(&StepSource::Script(owned), &name, e, &mut errors)
+            {
+                errors.push(fatal);
+            }
+            self.start_errors.extend(errors);
+        }
+    }
+
+    /// Attach a script to an existing object and start it.
+    pub fn add_script(
+        &mut self,
+        id: WorldObjectId,
+        script: Rfc<dyn ObjectScript>,
+    ) -> Result<(), String> {
+        self.get_world_obj_mut(&id)
+            .ok_or(format!("No world object {:?}", id))?
+            .add_script(script.clone());
+        self.invalidate_step_entries();
+        self.start_script(id, &script);
+        Ok(())
+    }
+
+    /// Make a prefab available to `instantiate_prefab`, replacing any
+    /// with the same name. Instances of a replaced prefab keep its mesh.
+    pub fn register_prefab(&mut self, name: &str, prefab: Prefab) {
+        self.prefabs.insert(name.to_owned(), prefab);
+        self.prefab_items.remove(name);
+    }
+
+    /// Add a copy of a registered prefab and its child prefabs, returning
+    /// the root's id. `tf` replaces the prefab's own transform.
+    /// Each object's scripts start once its children exist.
+    pub fn instantiate_prefab(
+        &mut self,
+        name: &str,
+        tf: Option<Transform>,
+    ) -> Result<WorldObjectId, String> {
+        self.check_prefab(name, &mut vec![])?;
+        self.instantiate_prefab_under(name, tf, None)
+    }
+
+    /// Make sure a prefab and every prefab below it exist, and that none
+    /// contains itself.
+    fn check_prefab(&self, name: &str, ancestors: &mut Vec<String>) -> Result<(), String> {
+        if ancestors.iter().any(|a| a == name) {
+            return Err(format!("Prefab {} contains itself", name));
+        }
+        let prefab = self
+            .prefabs
+            .get(name)
+            .ok_or(format!("No prefab named {}", name))?;
+        ancestors.push(name.to_owned());
+        for child in prefab.children.iter() {
+            self.check_prefab(&child.prefab, ancestors)?;
+        }
+        ancestors.pop();
+        Ok(())
+    }
+
+    /// Add a checked prefab. If any part of it fails, whatever was added
+    /// is removed again.
+    fn instantiate_prefab_under(
+        &mut self,
+        name: &str,
+        tf: Option<Transform>,
+        parent: Option<WorldObjectId>,
+    ) -> Result<WorldObjectId, String> {
+        let prefab = self
+            .prefabs
+            .get(name)
+            .ok_or(format!("No prefab named {}", name))?
+            .clone();
+        let (mesh, render, instanced, mesh_ref) = match prefab.mesh {
+            Some(m) => match &m.instanced_program_data {
+                Some(program_data) => (
+                    None,
+                    None,
+                    Some(InstancedComponent {
+                        item: self.prefab_item(name, &m.mesh, program_data)?,
+                        material: m.material,
+                    }),
+                    m.mesh_ref,
+                ),
+                None => (
+                    Some(MeshComponent::from_indexed(&m.mesh)),
+                    Some(RenderComponent {
+                        gl_program_data: m.program_data,
+                        renderer: self.get_renderer().ok_or("No renderer")?,
+                        material: m.material,
+                    }),
+                    None,
+                    m.mesh_ref,
+                ),
+            },
+            None => (None, None, None, None),
+        };
+        let id = self.add_world_obj(
+            WorldObject3DInit {
+                name: name.to_owned(),
+                tf: tf.unwrap_or(prefab.tf),
+                mesh,
+                render,
+                instanced,
+                mesh_ref,
+                parent_id: parent,
+                ..Default::default()
+            }
+            .init(),
+        );
+        for child in prefab.children {
+            if let Err(e) = self.instantiate_prefab_under(&child.prefab, child.tf, Some(id)) {
+                self.remove_world_obj(id, ChildPolicy::Remove)?;
+                return Err(e);
+            }
+        }
+        for make_script in prefab.scripts.iter() {
+            self.add_script(id, make_script())?;
+        }
+        Ok(id)
+    }
+
+    /// The instanced item drawing every instance of an instanced prefab.
+    fn prefab_item(
+        &mut self,
+        name: &str,
+        mesh: &IndexedMesh,
+        program_data: &ProgramData,
+    ) -> Result<Rc<InstancedRenderItem>, String> {
+        if let Some(item) = self.prefab_items.get(name) {
+            return Ok(item.clone());
+        }
+        let item = self.new_instanced_item(mesh, program_data)?;
+        self.prefab_items.insert(name.to_owned(), item.clone());
+        Ok(item)
+    }
+
+    /// Upload a mesh once, to be drawn for every instance added to it.
+    fn new_instanced_item(
+        &mut self,
+        mesh: &IndexedMesh,
+        program_data: &ProgramData,
+    ) -> Result<Rc<InstancedRenderItem>, String> {
+        let renderer = self.get_renderer().ok_or("No renderer")?;
+        let ctx = renderer.get_ctx();
+        let buffers = BufferInfo::new(ctx.clone())
+            .add_buffer(
+                "a_position".into(),
+                mesh.positions.clone(),
+                BufferSettings::new(3, Gl::FLOAT),
+            )
+            .add_buffer(
+                "a_normal".into(),
+                mesh.normals.clone(),
+                BufferSettings::new(3, Gl::FLOAT),
+            )
+            .set_indices(mesh.indices.clone());
+        Ok(self.add_instanced_item(InstancedRenderItem::new(
+            ctx,
+            program_data.clone(),
+            buffers,
+        )?))
+    }
+
+    pub fn rename_world_obj(&mut self, id: WorldObjectId, name: &str) -> Result<(), String> {
+        let obj = self
+            .world_objs
+            .get_mut(id.0)
+            .ok_or(format!("No world object {:?}", id))?;
+        self.index.rename(id, obj.get_name(), name);
+        obj.set_name(name.to_owned());
+        Ok(())
+    }
+
+    pub fn add_tag(&mut self, id: WorldObjectId, tag: &str) -> Result<(), String> {
+        let obj = self
+            .world_objs
+            .get_mut(id.0)
+            .ok_or(format!("No world object {:?}", id))?;
+        obj.tags_mut().insert(tag.to_owned());
+        self.index.add_tag(id, tag);
+        Ok(())
+    }
+
+    pub fn remove_tag(&mut self, id: WorldObjectId, tag: &str) -> Result<(), String> {
+        let obj = self
+            .world_objs
+            .get_mut(id.0)
+            .ok_or(format!("No world object {:?}", id))?;
+        obj.tags_mut().remove(tag);
+        self.index.remove_tag(id, tag);
+        Ok(())
+    }
+
+    /// Return the first object with the given name, by id.
+    pub fn find_by_name(&self, name: &str) -> Option<WorldObjectId> {
+        self.index.with_name(name).first().copied()
+    }
+
+    pub fn find_all_by_name(&self, name: &str) -> Vec<WorldObjectId> {
+        self.index.with_name(name)
+    }
+
+    /// Return every object having all of the given tags.
+    pub fn find_by_tags(&self, tags: &[&str]) -> Vec<WorldObjectId> {
+        self.index.with_tags(tags)
+    }
+
+    /// Find an object by the names of it and its ancestors, separated by
+    /// slashes and starting from a root object, such as `"cottage/roof"`.
+    /// Where names repeat, every match is searched, and the first full
+    /// match by id is returned.
+    pub fn find_by_path(&self, path: &str) -> Option<WorldObjectId> {
+        let names: Vec<&str> = path.split('/').filter(|n| !n.is_empty()).collect();
+        let (root_name, rest) = names.split_first()?;
+        self.index
+            .with_name(root_name)
+            .into_iter()
+            .filter(|id| self.world_objs[id.0].get_parent_id().is_none())
+            .find_map(|root| self.find_path_below(root, rest))
+    }
+
+    /// Follow `names` down through the children of `id`, depth first.
+    fn find_path_below(&self, id: WorldObjectId, names: &[&str]) -> Option<WorldObjectId> {
+        let (name, rest) = match names.split_first() {
+            Some(split) => split,
+            None => return Some(id),
+        };
+        self.world_objs[id.0]
+            .get_children_ids()
+            .iter()
+            .filter(|child| self.world_objs[child.0].get_name() == *name)
+            .find_map(|&child| self.find_path_below(child, rest))
+    }
+
+    /// Remove a world object, taking it out of its parent, the render
+    /// queues and its instanced mesh, and freeing its GPU resources. If
+    /// this removes the main camera, another camera takes over.
+    ///
+    /// Nothing is removed unless every object to be touched exists.
+    pub fn remove_world_obj(
+        &mut self,
+        id: WorldObjectId,
+        children: ChildPolicy,
+    ) -> Result<(), String> {
+        let obj = self
+            .get_world_obj(&id)
+            .ok_or(format!("No world object {:?}", id))?;
+        let parent = obj.get_parent_id();
+        let child_ids = obj.get_children_ids().clone();
+        let mut doomed = vec![id];
+        let mut unchecked = child_ids.clone();
+        while let Some(child) = unchecked.pop() {
+            let child_obj = self
+                .get_world_obj(&child)
+                .ok_or(format!("No world object {:?}", child))?;
+            if children == ChildPolicy::Remove {
+                doomed.push(child);
+                unchecked.extend(child_obj.get_children_ids().iter().copied());
+            }
+        }
+
+        if let Some(parent) = parent.and_then(|p| self.get_world_obj_mut(&p)) {
+            parent.remove_child_id(id);
+        }
+        for &doomed_id in &doomed {
+            self.destroy_world_obj(doomed_id);
+        }
+        if children == ChildPolicy::Reparent {
+            for child in child_ids {
+                self.get_world_obj_mut(&child).unwrap().set_parent_id(None);
+                self.set_parent(child, parent)?;
+            }
+        }
+        if self.main_camera.is_some_and(|cam| doomed.contains(&cam)) {
+            self.replace_main_camera();
+        }
+        Ok(())
+    }
+
+    /// Remove a single object which is known to exist, leaving its
+    /// children and parent alone.
+    fn destroy_world_obj(&mut self, id: WorldObjectId) {
+        let obj = self.world_objs.remove(id.0);
+        self.index.remove(id, obj.get_name(), obj.get_tags());
+        if !obj.get_scripts().is_empty() {
+            self.invalidate_step_entries();
+        }
+        if let Some(item) = &obj.render_item {
+            self.renderables.remove_item(item);
+            if let Some(renderer) = &self.renderer {
+                item.delete_gl_resources(renderer.get_ctx());
+            }
+        }
+        if let Some(instance) = &obj.instance {
+            instance.item.remove_instance(instance.key);
+        }
+        self.components.remove_all(id);
+        self.events.publish(ObjectDestroyed(id));
+    }
+
+    /// Hand rendering to the first remaining camera. With none left, the
+    /// renderer gets a detached copy of the old camera, so it no longer
+    /// follows a removed object.
+    fn replace_main_camera(&mut self) {
+        self.main_camera = None;
+        let next = self
+            .world_objs
+            .iter()
+            .find(|(_, obj)| obj.camera.is_some())
+            .map(|(idx, _)| WorldObjectId(idx));
+        if let Some(next) = next {
+            self.set_main_camera(next);
+        } else if let Some(renderer) = &self.renderer {
+            let detached = {
+                let old = renderer.get_camera();
+                let old = old.borrow();
+                let detached = Camera::new(old.get_projection(), old.get_viewport());
+                *detached.get_tf_rc().borrow_mut() = old.get_tf_rc().borrow().clone();
+                detached
+            };
+            renderer.set_camera(Rc::new(RefCell::new(detached)));
+        }
+    }
+
+    /// Attach a component to an object, returning the component of the
+    /// same type it replaced.
+    pub fn add_component<T: 'static>(
+        &mut self,
+        id: WorldObjectId,
+        component: T,
+    ) -> Result<Option<T>, String> {
+        if !self.world_objs.contains(id.0) {
+            return Err(format!("No world object {:?}", id));
+        }
+        Ok(self.components.insert(id, component))
+    }
+
+    pub fn get_component<T: 'static>(&self, id: WorldObjectId) -> Option<&T> {
+        self.components.get(id)
+    }
+
+    pub fn get_component_mut<T: 'static>(&mut self, id: WorldObjectId) -> Option<&mut T> {
+        self.components.get_mut(id)
+    }
+
+    pub fn remove_component<T: 'static>(&mut self, id: WorldObjectId) -> Option<T> {
+        self.components.remove(id)
     }
 
-    pub fn add_scripted_component(&mut self, s: impl Steppable<Self> + 'static) {
-        self.scripted_components.push(Rc::new(RefCell::new(s)));
+    /// Save components of type `T` in scenes, under `name`.
+    pub fn register_component<T: Serialize + DeserializeOwned + 'static>(&mut self, name: &str) {
+        self.components.register::<T>(name);
+    }
+
+    /// Return every object with all of the given components, such as
+    /// `query::<(Health, Rigidbody)>()`.
+    pub fn query<'a, Q: Query<'a>>(&'a self) -> Vec<(WorldObjectId, Q::Item)> {
+        self.components.query::<Q>()
+    }
+
+    /// Move `child` under `parent`, or make it a root object with `None`.
+    /// The child keeps its local transform, so moves with its new parent.
+    pub fn set_parent(
+        &mut self,
+        child: WorldObjectId,
+        parent: Option<WorldObjectId>,
+    ) -> Result<(), String> {
+        let old_parent = self
+            .get_world_obj(&child)
+            .ok_or(format!("No world object {:?}", child))?
+            .get_parent_id();
+        // Walk up from the new parent to make sure we aren't one of its
+        // ancestors.
+        let mut ancestor = parent;
+        while let Some(id) = ancestor {
+            if id == child {
+                return Err(format!("{:?} can't be its own ancestor", child));
+            }
+            ancestor = self
+                .get_world_obj(&id)
+                .ok_or(format!("No world object {:?}", id))?
+                .get_parent_id();
+        }
+        if let Some(old) = old_parent.and_then(|p| self.get_world_obj_mut(&p)) {
+            old.remove_child_id(child);
+        }
+        if let Some(new) = parent.and_then(|p| self.get_world_obj_mut(&p)) {
+            new.add_child_id(child);
+        }
+        self.get_world_obj_mut(&child)
+            .unwrap()
+            .set_parent_id(parent);
+        Ok(())
+    }
+
+    /// Recompute the world matrices of every object whose transform, or
+    /// any of whose ancestors' transforms, changed since the last update.
+    pub fn update_world_transforms(&mut self) {
+        let roots: Vec<WorldObjectId> = self
+            .world_objs
+            .iter()
+            .filter(|(_, obj)| obj.get_parent_id().is_none())
+            .map(|(idx, _)| WorldObjectId(idx))
+            .collect();
+        for root in roots {
+            self.update_world_tf(root, None, false);
+        }
+    }
+
+    fn update_world_tf(
+        &self,
+        id: WorldObjectId,
+        parent_world: Option<&Matrix4<f32>>,
+        parent_changed: bool,
+    ) {
+        let obj = match self.get_world_obj(&id) {
+            Some(obj) => obj,
+            None => return,
+        };
+        let changed = {
+            let mut tf = obj.tf_rc.borrow_mut();
+            let changed = tf.take_changed() || parent_changed;
+            if changed {
+                tf.set_parent_world(parent_world.copied());
+                if let Some(instance) = &obj.instance {
+                    instance.item.mark_moved();
+                }
+            }
+            changed
+        };
+        let children = obj.get_children_ids();
+        if children.is_empty() {
+            return;
+        }
+        let world = obj.tf_rc.borrow().world_mat();
+        for child in children {
+            self.update_world_tf(*child, Some(&world), changed);
+        }
+    }
+
+    /// Add a mesh set as a hierarchy of world objects: a root named `name`,
+    /// with a child per mesh object named after it, each with a child per
+    /// group. Groups draw their triangles themselves, and their lines and
+    /// points through children named "lines" and "points".
+    /// The root is placed at `tf`, everything below it at the identity.
+    /// Giving the `mesh_ref` the set was loaded from, with a `Split`
+    /// layout, lets the root be saved in scenes.
+    pub fn add_mesh_set(
+        &mut self,
+        name: &str,
+        set: MeshSet,
+        materials: &BTreeMap<String, Material>,
+        program_data: &ProgramData,
+        tf: &Transform,
+        mesh_ref: Option<MeshRef>,
+    ) -> Result<WorldObjectId, String> {
+        let object_ids = self.add_mesh_set_objects(set, materials, program_data)?;
+        Ok(self.add_world_obj(
+            WorldObject3DInit {
+                name: name.to_owned(),
+                tf: tf.clone(),
+                children_ids: object_ids,
+                mesh_ref,
+                ..Default::default()
+            }
+            .init(),
+        ))
+    }
+
+    /// Add the objects of a mesh set without a root, returning their ids.
+    fn add_mesh_set_objects(
+        &mut self,
+        set: MeshSet,
+        materials: &BTreeMap<String, Material>,
+        program_data: &ProgramData,
+    ) -> Result<Vec<WorldObjectId>, String> {
+        let mut object_ids = vec![];
+        for object in set.objects {
+            let mut group_ids = vec![];
+            for group in object.groups {
+                let material = group
+                    .material_name
+                    .as_ref()
+                    .and_then(|name| materials.get(name).cloned())
+                    .unwrap_or_default();
+                group_ids.push(self.add_mesh_group(group, material, program_data)?);
+            }
+            let object_id = self.add_world_obj(
+                WorldObject3DInit {
+                    name: object.name,
+                    children_ids: group_ids,
+                    ..Default::default()
+                }
+                .init(),
+            );
+            object_ids.push(object_id);
+        }
+        Ok(object_ids)
+    }
+
+    fn add_mesh_group(
+        &mut self,
+        group: MeshGroup,
+        material: Material,
+        program_data: &ProgramData,
+    ) -> Result<WorldObjectId, String> {
+        let renderer = self.get_renderer().ok_or("No renderer")?;
+        let MeshGroup {
+            name,
+            material_name,
+            mesh,
+            line_indices,
+            point_indices,
+        } = group;
+        let mut child_ids = vec![];
+        for (child_name, indices, draw_type) in [
+            ("lines", &line_indices, Gl::LINES),
+            ("points", &point_indices, Gl::POINTS),
+        ] {
+            if indices.is_empty() {
+                continue;
+            }
+            // Lines and points usually use few of the group's vertices, so
+            // give them only those.
+            let (subset, indices) = mesh.subset(indices);
+            let child = WorldObject3DInit {
+                name: child_name.to_owned(),
+                mesh: Some(MeshComponent {
+                    data: subset.positions,
+                    indices: Some(indices),
+                    normals: Some(subset.normals),
+                    texcoords: Some(subset.texcoords).filter(|t| !t.is_empty()),
+                    draw_type,
+                }),
+                render: Some(RenderComponent {
+                    gl_program_data: program_data.clone(),
+                    renderer: renderer.clone(),
+                    material: material.clone(),
+                }),
+                ..Default::default()
+            };
+            child_ids.push(self.add_world_obj(child.init()));
+        }
+        let has_triangles = !mesh.indices.is_empty();
+        let mesh = if child_ids.is_empty() {
+            mesh
+        } else {
+            // Drop vertices only the lines and points use.
+            let (mut subset, indices) = mesh.subset(&mesh.indices);
+            subset.indices = indices;
+            subset
+        };
+        let texcoords = Some(mesh.texcoords).filter(|t| !t.is_empty());
+        let group_obj = WorldObject3DInit {
+            name: name.or(material_name).unwrap_or_else(|| "default".into()),
+            mesh: Some(MeshComponent {
+                data: mesh.positions,
+                indices: Some(mesh.indices),
+                normals: Some(mesh.normals),
+                texcoords,
+                draw_type: Gl::TRIANGLES,
+            })
+            .filter(|_| has_triangles),
+            render: Some(RenderComponent {
+                gl_program_data: program_data.clone(),
+                renderer,
+                material,
+            })
+            .filter(|_| has_triangles),
+            children_ids: child_ids,
+            ..Default::default()
+        };
+        Ok(self.add_world_obj(group_obj.init()))
+    }
+
+    /// Queue an event for the subscribers of its type, delivered by
+    /// `deliver_events`.
+    pub fn publish<T: 'static>(&mut self, event: T) {
+        self.events.publish(event);
+    }
+
+    pub fn subscribe<T: 'static>(
+        &mut self,
+        handler: impl FnMut(&T, &mut WorldState) -> Result<(), StepError<String>> + 'static,
+    ) -> SubscriptionId {
+        self.events.subscribe(handler)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.events.unsubscribe(id)
+    }
+
+    /// Hand every queued event to its subscribers, in publish order.
+    /// Events published while delivering wait for the next delivery.
+    /// Subscriber errors are handled as in a step phase, with
+    /// `SelfDisable` unsubscribing.
+    pub fn deliver_events(&mut self) -> Result<(), String> {
+        let mut post_phase_errors = vec![];
+        for delivery in self.events.take_deliveries() {
+            for (id, handler) in delivery.handlers {
+                let result = (handler.borrow_mut())(&*delivery.event, self);
+                if let Err(e) = result {
+                    let name = format!("{:?} to {}", id, delivery.event_name);
+                    let source = StepSource::Subscriber(id);
+                    self.handle_step_error(&source, &name, e, &mut post_phase_errors)?;
+                }
+            }
+        }
+        join_errors(post_phase_errors)
+    }
+
+    /// Save every object, except cameras, with its registered components.
+    /// Objects drawing geometry built in code, rather than loaded from a
+    /// url, are skipped along with their children, with a warning.
+    pub fn save_scene(&self) -> Result<SceneData, String> {
+        let mut objects = vec![];
+        for (idx, obj) in self.world_objs.iter() {
+            if obj.get_parent_id().is_none() {
+                if let Some(saved) = self.save_scene_object(WorldObjectId(idx))? {
+                    objects.push(saved);
+                }
+            }
+        }
+        Ok(SceneData { objects })
+    }
+
+    fn save_scene_object(&self, id: WorldObjectId) -> Result<Option<SceneObject>, String> {
+        let obj = match self.get_world_obj(&id) {
+            Some(obj) if obj.camera.is_none() => obj,
+            _ => return Ok(None),
+        };
+        let mesh = obj.mesh_ref.clone();
+        if mesh.is_none() && (obj.render_item.is_some() || obj.instance.is_some()) {
+            js_bindings::warn(&format!(
+                "Not saving {:?} {:?}, as its mesh wasn't loaded from a url",
+                id,
+                obj.get_name()
+            ));
+            return Ok(None);
+        }
+        let mut children = vec![];
+        // A split mesh's children are made again when it's loaded.
+        let split = mesh.as_ref().map(|m| m.layout) == Some(MeshLayout::Split);
+        for child in obj.get_children_ids().iter().filter(|_| !split) {
+            if let Some(saved) = self.save_scene_object(*child)? {
+                children.push(saved);
+            }
+        }
+        Ok(Some(SceneObject {
+            name: obj.get_name().to_owned(),
+            tags: obj.get_tags().clone(),
+            transform: TransformData::from_transform(&obj.tf_rc.borrow()),
+            mesh,
+            components: self.components.save_registered(id)?,
+            children,
+        }))
+    }
+
+    /// Add every object in a scene, returning the ids of its root objects.
+    pub fn load_scene(
+        &mut self,
+        scene: &SceneData,
+        assets: &SceneAssets,
+    ) -> Result<Vec<WorldObjectId>, String> {
+        let mut parsed = ParsedMeshes::new();
+        scene
+            .objects
+            .iter()
+            .map(|obj| self.load_scene_object(obj, None, assets, &mut parsed))
+            .collect()
+    }
+
+    fn load_scene_object(
+        &mut self,
+        saved: &SceneObject,
+        parent: Option<WorldObjectId>,
+        assets: &SceneAssets,
+        parsed: &mut ParsedMeshes,
+    ) -> Result<WorldObjectId, String> {
+        let mut init = WorldObject3DInit {
+            name: saved.name.clone(),
+            tags: saved.tags.clone(),
+            tf: saved.transform.to_transform(),
+            mesh_ref: saved.mesh.clone(),
+            parent_id: parent,
+            ..Default::default()
+        };
+        if let Some(mesh_ref) = &saved.mesh {
+            self.load_mesh_ref(mesh_ref, assets, parsed, &mut init)?;
+        }
+        let id = self.add_world_obj(init.init());
+        for (name, value) in saved.components.iter() {
+            self.components.load_registered(id, name, value.clone())?;
+        }
+        for child in saved.children.iter() {
+            self.load_scene_object(child, Some(id), assets, parsed)?;
+        }
+        Ok(id)
+    }
+
+    /// Set up `init` to draw a mesh as its `layout` says.
+    fn load_mesh_ref(
+        &mut self,
+        mesh_ref: &MeshRef,
+        assets: &SceneAssets,
+        parsed: &mut ParsedMeshes,
+        init: &mut WorldObject3DInit,
+    ) -> Result<(), String> {
+        let url = &mesh_ref.url;
+        let data = assets
+            .mesh_datas
+            .get(url)
+            .ok_or(format!("Mesh {} is not loaded", url))?;
+        let program = assets
+            .programs
+            .get(&mesh_ref.program)
+            .ok_or(format!("No GL program named {}", mesh_ref.program))?;
+        let materials = match material_library_url(url, data) {
+            Some(mtl_url) => {
+                let mtl_data = assets
+                    .mesh_datas
+                    .get(&mtl_url)
+                    .ok_or(format!("Material library {} is not loaded", mtl_url))?;
+                parsed.materials(&mtl_url, mtl_data)?
+            }
+            None => Rc::new(BTreeMap::new()),
+        };
+        let material = match &mesh_ref.material {
+            Some(name) => materials
+                .get(name)
+                .cloned()
+                .ok_or(format!("No material named {} for {}", name, url))?,
+            None => Material::default(),
+        };
+        match mesh_ref.layout {
+            MeshLayout::Single => {
+                init.mesh = Some(MeshComponent::from_indexed(&*parsed.mesh(url, data)?));
+                init.render = Some(RenderComponent {
+                    gl_program_data: program.clone(),
+                    renderer: self.get_renderer().ok_or("No renderer")?,
+                    material,
+                });
+            }
+            MeshLayout::Instanced => {
+                let item = match self.scene_items.get(mesh_ref) {
+                    Some(item) => item.clone(),
+                    None => {
+                        let item = self.new_instanced_item(&*parsed.mesh(url, data)?, program)?;
+                        self.scene_items.insert(mesh_ref.clone(), item.clone());
+                        item
+                    }
+                };
+                init.instanced = Some(InstancedComponent { item, material });
+            }
+            MeshLayout::Split => {
+                let set = (*parsed.mesh_set(url, data)?).clone();
+                init.children_ids = self.add_mesh_set_objects(set, &materials, program)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a camera as a world object, sharing the camera's transform.
+    /// The first camera added becomes the main camera.
+    pub fn add_camera(&mut self, camera: Rfc<Camera>) -> WorldObjectId {
+        let mut obj = WorldObject3DInit {
+            ..Default::default()
+        }
+        .init();
+        obj.tf_rc = camera.borrow().get_tf_rc();
+        obj.camera = Some(camera);
+        let id = self.add_world_obj(obj);
+        if self.main_camera.is_none() {
+            self.set_main_camera(id);
+        }
+        id
+    }
+
+    /// Make the camera attached to the given object the one rendered from.
+    pub fn set_main_camera(&mut self, id: WorldObjectId) -> Option<Rfc<Camera>> {
+        let camera = self.get_world_obj(&id)?.camera.clone()?;
+        if let Some(r) = &self.renderer {
+            r.set_camera(camera.clone());
+        }
+        self.main_camera = Some(id);
+        Some(camera)
+    }
+
+    pub fn get_main_camera_id(&self) -> Option<WorldObjectId> {
+        self.main_camera
+    }
+
+    pub fn get_main_camera(&self) -> Option<Rfc<Camera>> {
+        self.main_camera
+            .and_then(|id| self.get_world_obj(&id))
+            .and_then(|obj| obj.camera.clone())
+    }
+
+    /// Return the global steppables, followed by the scripts of every
+    /// object.
+    pub fn scripted_components(&self) -> Rc<[StepEntry]> {
+        if let Some(entries) = &*self.step_entries.borrow() {
+            return entries.clone();
+        }
+        let mut steppables: Vec<StepEntry> = self
+            .scripted_components
+            .iter()
+            .map(|(id, s)| StepEntry {
+                source: StepSource::Global(*id),
+                steppable: s.clone(),
+            })
+            .collect();
+        for (idx, obj) in self.world_objs.iter() {
+            for script in obj.get_scripts() {
+                let owned = OwnedScript {
+                    owner: WorldObjectId(idx),
+                    script: script.clone(),
+                };
+                steppables.push(StepEntry {
+                    source: StepSource::Script(owned.clone()),
+                    steppable: Rc::new(RefCell::new(owned)),
+                });
+            }
+        }
+        let entries: Rc<[StepEntry]> = steppables.into();
+        *self.step_entries.borrow_mut() = Some(entries.clone());
+        entries
+    }
+
+    fn invalidate_step_entries(&mut self) {
+        *self.step_entries.get_mut() = None;
+    }
+
+    /// Stop stepping a steppable which returned `SelfDisable`.
+    fn disable(&mut self, source: &StepSource) {
+        match source {
+            StepSource::Global(id) => {
+                self.remove_scripted_component(*id);
+            }
+            StepSource::Script(owned) => {
+                if let Some(obj) = self.get_world_obj_mut(&owned.owner) {
+                    obj.remove_script(&owned.script);
+                }
+                self.invalidate_step_entries();
+            }
+            StepSource::Subscriber(id) => {
+                self.unsubscribe(*id);
+            }
+        }
+    }
+
+    /// Add a global steppable without starting it. Only safe outside of
+    /// stepping; steppables should use `defer_add_scripted_component`.
+    pub fn add_scripted_component(&mut self, s: impl Steppable<Self> + 'static) -> SteppableId {
+        let id = self.reserve_steppable_id();
+        self.scripted_components
+            .push((id, Rc::new(RefCell::new(s))));
+        self.invalidate_step_entries();
+        id
+    }
+
+    pub fn remove_scripted_component(&mut self, id: SteppableId) -> bool {
+        let before = self.scripted_components.len();
+        self.scripted_components.retain(|(s_id, _)| *s_id != id);
+        self.invalidate_step_entries();
+        before != self.scripted_components.len()
+    }
+
+    fn reserve_steppable_id(&mut self) -> SteppableId {
+        let id = SteppableId(self.next_steppable_id);
+        self.next_steppable_id += 1;
+        id
+    }
+
+    /// Queue a change to be applied by `apply_commands`.
+    pub fn defer(&mut self, command: WorldCommand) {
+        self.commands.push(command);
+    }
+
+    /// Queue a global steppable to be added and started, returning the id
+    /// it will have.
+    pub fn defer_add_scripted_component(
+        &mut self,
+        s: impl Steppable<Self> + 'static,
+    ) -> SteppableId {
+        let id = self.reserve_steppable_id();
+        self.defer(WorldCommand::AddSteppable(id, Rc::new(RefCell::new(s))));
+        id
+    }
+
+    /// Apply every queued command in order, including any queued while
+    /// applying them. A failing command doesn't stop the rest; every
+    /// failure is returned together once the queue is empty, along with
+    /// fatal errors from starting object scripts since the last call.
+    pub fn apply_commands(&mut self) -> Result<(), String> {
+        let mut errors = vec![];
+        while !self.commands.is_empty() {
+            for command in std::mem::take(&mut self.commands) {
+                if let Err(e) = self.apply_command(command) {
+                    errors.push(e);
+                }
+            }
+        }
+        errors.append(&mut self.start_errors);
+        join_errors(errors)
+    }
+
+    fn apply_command(&mut self, command: WorldCommand) -> Result<(), String> {
+        match command {
+            WorldCommand::Spawn(init) => {
+                self.add_world_obj(init.init());
+            }
+            WorldCommand::SpawnPrefab { name, tf } => {
+                self.instantiate_prefab(&name, tf.map(|tf| *tf))?;
+            }
+            WorldCommand::Despawn(id, children) => self.remove_world_obj(id, children)?,
+            WorldCommand::AddSteppable(id, s) => {
+                self.scripted_components.push((id, s.clone()));
+                self.invalidate_step_entries();
+                let entry = StepEntry {
+                    source: StepSource::Global(id),
+                    steppable: s,
+                };
+                self.run_starts(&[entry])?;
+            }
+            WorldCommand::RemoveSteppable(id) => {
+                self.remove_scripted_component(id);
+            }
+            WorldCommand::Reparent { child, parent } => self.set_parent(child, parent)?,
+        }
+        Ok(())
+    }
+
+    /// Run one phase of stepping. Errors are reported under the name of
+    /// the steppable returning them. `SelfDisable` stops only that
+    /// steppable, while `FatalPostStep` ends stepping once the rest of the
+    /// phase has run.
+    fn run_phase(
+        &mut self,
+        steppables: &[StepEntry],
+        mut phase: impl FnMut(&mut WorldSteppable, &mut WorldState) -> Result<(), StepError<String>>,
+    ) -> Result<(), String> {
+        let mut post_phase_errors = vec![];
+        for entry in steppables.iter() {
+            let result = phase(&mut *entry.steppable.borrow_mut(), self);
+            if let Err(e) = result {
+                let name = entry.describe();
+                self.handle_step_error(&entry.source, &name, e, &mut post_phase_errors)?;
+            }
+        }
+        join_errors(post_phase_errors)
+    }
+
+    /// Report an error from the steppable `name`. Only `Fatal` is
+    /// returned; `FatalPostStep` is added to `post_phase_errors`.
+    fn handle_step_error(
+        &mut self,
+        source: &StepSource,
+        name: &str,
+        error: StepError<String>,
+        post_phase_errors: &mut Vec<String>,
+    ) -> Result<(), String> {
+        match error {
+            StepError::Ignore => {}
+            StepError::Recover(e) => {
+                js_bindings::warn(&format!("{}: {}", name, e));
+            }
+            StepError::SelfDisable(e) => {
+                js_bindings::warn(&format!("Disabled {}: {}", name, e));
+                self.disable(source);
+            }
+            StepError::FatalPostStep(e) => {
+                post_phase_errors.push(format!("{}: {}", name, e));
+            }
+            StepError::Fatal(e) => {
+                return Err(format!("{}: {}", name, e));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run_starts(self: &mut WorldState, steppables: &[StepEntry]) -> Result<(), String> {
+        self.run_phase(steppables, |s, state| s.start(state))
     }
 
     pub fn run_steps(
         self: &mut WorldState,
         game_loop: &GameLoop,
-        steppables: &mut Vec<Rc<RefCell<WorldSteppable>>>,
+        steppables: &[StepEntry],
     ) -> Result<(), String> {
-        for s in steppables.iter_mut() {
-            s.borrow_mut().step(self, game_loop).or_else(|e| e.translate())?;
-        }
-        Ok(())
+        self.run_phase(steppables, |s, state| s.step(state, game_loop))
     }
 
     pub fn run_fixed_steps(
         self: &mut WorldState,
         game_loop: &GameLoop,
-        steppables: &mut Vec<Rc<RefCell<WorldSteppable>>>,
+        steppables: &[StepEntry],
     ) -> Result<(), String> {
-        for s in steppables.iter_mut() {
-            s.borrow_mut().fixed_step(self).or_else(|e| e.translate())?;
-        }
-        Ok(())
+        self.run_phase(steppables, |s, state| s.fixed_step(state))
     }
 
     pub fn run_late_steps(
         self: &mut WorldState,
         game_loop: &GameLoop,
-        steppables: &mut Vec<Rc<RefCell<WorldSteppable>>>,
+        steppables: &[StepEntry],
     ) -> Result<(), String> {
-        for s in steppables.iter_mut() {
-            s.borrow_mut().late_step(self).or_else(|e| e.translate())?;
-        }
+        self.run_phase(steppables, |s, state| s.late_step(state))
+    }
+}
+
+/// Combine errors collected while carrying on past them.
+fn join_errors(errors: Vec<String>) -> Result<(), String> {
+    if errors.is_empty() {
         Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::camera::Viewport;
+    use crate::prefab::PrefabChild;
+    use crate::scene::SceneFormat;
+    use na::Vector3;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Health(u32);
+
+    fn add_named(
+        state: &mut WorldState,
+        name: &str,
+        parent: Option<WorldObjectId>,
+    ) -> WorldObjectId {
+        state.add_world_obj(
+            WorldObject3DInit {
+                name: name.to_owned(),
+                parent_id: parent,
+                ..Default::default()
+            }
+            .init(),
+        )
+    }
+
+    fn camera() -> Rfc<Camera> {
+        Rc::new(RefCell::new(Camera::new(
+            Projection::Orthographic {
+                view_width: 2.,
+                view_height: 2.,
+                clip_depth: 2.,
+            },
+            Viewport::new(0., 0., 2., 2.),
+        )))
+    }
+
+    #[test]
+    fn added_parent_takes_children_from_their_old_one() {
+        let mut state = WorldState::new();
+        let old = add_named(&mut state, "old", None);
+        let child = add_named(&mut state, "child", Some(old));
+        let new = state.add_world_obj(
+            WorldObject3DInit {
+                name: "new".into(),
+                children_ids: vec![child],
+                ..Default::default()
+            }
+            .init(),
+        );
+        assert!(state
+            .get_world_obj(&old)
+            .unwrap()
+            .get_children_ids()
+            .is_empty());
+        assert_eq!(
+            state.get_world_obj(&new).unwrap().get_children_ids(),
+            &vec![child]
+        );
+        assert_eq!(
+            state.get_world_obj(&child).unwrap().get_parent_id(),
+            Some(new)
+        );
+    }
+
+    #[test]
+    fn moving_a_parent_only_updates_its_subtree() {
+        let mut state = WorldState::new();
+        let moved = add_named(&mut state, "moved", None);
+        let child = add_named(&mut state, "child", Some(moved));
+        let still = add_named(&mut state, "still", None);
+        let still_child = add_named(&mut state, "still_child", Some(still));
+        state.update_world_transforms();
+
+        state
+            .get_world_obj_mut(&moved)
+            .unwrap()
+            .set_local_position(Vector3::new(1., 2., 3.));
+        // A stale parent matrix left in place shows the subtree was skipped.
+        let stale = Matrix4::new_translation(&Vector3::new(9., 9., 9.));
+        state
+            .get_world_obj(&still_child)
+            .unwrap()
+            .tf_rc
+            .borrow_mut()
+            .set_parent_world(Some(stale));
+        state.update_world_transforms();
+
+        assert_eq!(
+            state.get_world_obj(&child).unwrap().get_world_mat(),
+            Matrix4::new_translation(&Vector3::new(1., 2., 3.))
+        );
+        assert_eq!(
+            state.get_world_obj(&still_child).unwrap().get_world_mat(),
+            stale
+        );
+    }
+
+    #[test]
+    fn removing_a_parent_removes_its_subtree() {
+        let mut state = WorldState::new();
+        let root = add_named(&mut state, "root", None);
+        let parent = add_named(&mut state, "parent", Some(root));
+        let child = add_named(&mut state, "child", Some(parent));
+        let grandchild = add_named(&mut state, "grandchild", Some(child));
+        state.add_tag(grandchild, "leaf").unwrap();
+
+        state.remove_world_obj(parent, ChildPolicy::Remove).unwrap();
+
+        for id in [parent, child, grandchild].iter() {
+            assert!(state.get_world_obj(id).is_none());
+        }
+        assert!(state
+            .get_world_obj(&root)
+            .unwrap()
+            .get_children_ids()
+            .is_empty());
+        assert_eq!(state.find_by_name("child"), None);
+        assert!(state.find_by_tags(&["leaf"]).is_empty());
+        assert_eq!(state.find_by_path("root"), Some(root));
+    }
+
+    #[test]
+    fn removing_a_parent_can_hand_children_up() {
+        let mut state = WorldState::new();
+        let root = add_named(&mut state, "root", None);
+        let parent = add_named(&mut state, "parent", Some(root));
+        let child = add_named(&mut state, "child", Some(parent));
+        state
+            .get_world_obj_mut(&child)
+            .unwrap()
+            .set_local_position(Vector3::new(1., 0., 0.));
+        state.add_tag(parent, "doomed").unwrap();
+
+        state
+            .remove_world_obj(parent, ChildPolicy::Reparent)
+            .unwrap();
+
+        assert!(state.get_world_obj(&parent).is_none());
+        assert_eq!(
+            state.get_world_obj(&root).unwrap().get_children_ids(),
+            &vec![child]
+        );
+        assert_eq!(
+            state.get_world_obj(&child).unwrap().get_local_position(),
+            Vector3::new(1., 0., 0.)
+        );
+        assert_eq!(state.find_by_name("parent"), None);
+        assert!(state.find_by_tags(&["doomed"]).is_empty());
+        assert_eq!(state.find_by_path("root/child"), Some(child));
+    }
+
+    #[test]
+    fn removing_a_missing_child_removes_nothing() {
+        let mut state = WorldState::new();
+        let parent = add_named(&mut state, "parent", None);
+        let child = add_named(&mut state, "child", Some(parent));
+        state.world_objs.remove(child.0);
+
+        assert!(state.remove_world_obj(parent, ChildPolicy::Remove).is_err());
+        assert!(state.get_world_obj(&parent).is_some());
+    }
+
+    #[test]
+    fn removing_the_main_camera_hands_over_to_another() {
+        let mut state = WorldState::new();
+        let first = state.add_camera(camera());
+        let second = state.add_camera(camera());
+        assert_eq!(state.get_main_camera_id(), Some(first));
+
+        state.remove_world_obj(first, ChildPolicy::Remove).unwrap();
+        assert_eq!(state.get_main_camera_id(), Some(second));
+
+        state.remove_world_obj(second, ChildPolicy::Remove).unwrap();
+        assert_eq!(state.get_main_camera_id(), None);
+    }
+
+    #[test]
+    fn saved_scenes_load_back() {
+        let mut state = WorldState::new();
+        state.register_component::<Health>("health");
+        let house = add_named(&mut state, "house", None);
+        state
+            .get_world_obj_mut(&house)
+            .unwrap()
+            .set_local_position(Vector3::new(1., 2., 3.));
+        state.add_tag(house, "building").unwrap();
+        state.add_component(house, Health(10)).unwrap();
+        add_named(&mut state, "door", Some(house));
+        state.add_camera(camera());
+
+        let scene = state.save_scene().unwrap();
+        let text = scene.to_text(SceneFormat::Ron).unwrap();
+        let mut loaded = WorldState::new();
+        loaded.register_component::<Health>("health");
+        let assets = SceneAssets {
+            mesh_datas: &BTreeMap::new(),
+            programs: &BTreeMap::new(),
+        };
+        let roots = loaded
+            .load_scene(
+                &SceneData::from_text(&text, SceneFormat::Ron).unwrap(),
+                &assets,
+            )
+            .unwrap();
+
+        let house = loaded.find_by_path("house").unwrap();
+        assert_eq!(roots, vec![house]);
+        let obj = loaded.get_world_obj(&house).unwrap();
+        assert_eq!(obj.get_local_position(), Vector3::new(1., 2., 3.));
+        assert!(obj.has_tag("building"));
+        assert_eq!(loaded.get_component::<Health>(house), Some(&Health(10)));
+        assert!(loaded.find_by_path("house/door").is_some());
+        assert_eq!(loaded.save_scene().unwrap(), scene);
+    }
+
+    fn at(x: f32) -> Transform {
+        let mut tf = Transform::identity();
+        tf.set_position(Vector3::new(x, 0., 0.));
+        tf
+    }
+
+    fn prefab_with_children(children: &[(&str, Option<Transform>)]) -> Prefab {
+        Prefab {
+            children: children
+                .iter()
+                .map(|(prefab, tf)| PrefabChild {
+                    prefab: (*prefab).to_owned(),
+                    tf: tf.clone(),
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prefab_transform_can_be_overridden() {
+        let mut state = WorldState::new();
+        state.register_prefab(
+            "lamp",
+            Prefab {
+                tf: at(1.),
+                ..Default::default()
+            },
+        );
+        let own = state.instantiate_prefab("lamp", None).unwrap();
+        let moved = state.instantiate_prefab("lamp", Some(at(2.))).unwrap();
+
+        let x = |id| state.get_world_obj(&id).unwrap().get_local_position().x;
+        assert_eq!(x(own), 1.);
+        assert_eq!(x(moved), 2.);
+    }
+
+    #[test]
+    fn prefabs_instantiate_their_children() {
+        let mut state = WorldState::new();
+        state.register_prefab(
+            "lamp",
+            Prefab {
+                tf: at(1.),
+                ..Default::default()
+            },
+        );
+        state.register_prefab("bulb", Prefab::default());
+        state.register_prefab("post", prefab_with_children(&[("lamp", Some(at(3.)))]));
+        state.register_prefab(
+            "street",
+            prefab_with_children(&[("post", None), ("bulb", None)]),
+        );
+
+        let street = state.instantiate_prefab("street", None).unwrap();
+
+        assert_eq!(state.find_by_path("street"), Some(street));
+        assert_eq!(
+            state
+                .get_world_obj(&street)
+                .unwrap()
+                .get_children_ids()
+                .len(),
+            2
+        );
+        assert!(state.find_by_path("street/bulb").is_some());
+        let lamp = state.find_by_path("street/post/lamp").unwrap();
+        assert_eq!(
+            state.get_world_obj(&lamp).unwrap().get_local_position().x,
+            3.
+        );
+    }
+
+    #[test]
+    fn broken_prefabs_add_nothing() {
+        let mut state = WorldState::new();
+        state.register_prefab("a", prefab_with_children(&[("b", None)]));
+        state.register_prefab("b", prefab_with_children(&[("a", None)]));
+        state.register_prefab("c", prefab_with_children(&[("b", None)]));
+        state.register_prefab("d", prefab_with_children(&[("missing", None)]));
+
+        assert!(state.instantiate_prefab("c", None).is_err());
+        assert!(state.instantiate_prefab("d", None).is_err());
+        assert!(state.instantiate_prefab("missing", None).is_err());
+        assert!(state.world_objs.is_empty());
+    }
+
+    /// Counts how often it's started and stepped.
+    #[derive(Clone, Default)]
+    struct Counter {
+        starts: Rc<Cell<u32>>,
+        steps: Rc<Cell<u32>>,
+    }
+
+    impl Steppable<WorldState> for Counter {
+        fn start(&mut self, _: &mut WorldState) -> Result<(), StepError<String>> {
+            self.starts.set(self.starts.get() + 1);
+            Ok(())
+        }
+
+        fn step(&mut self, _: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
+            self.steps.set(self.steps.get() + 1);
+            Ok(())
+        }
+    }
+
+    /// Queues an object named `name` when started.
+    #[derive(Clone)]
+    struct Spawner {
+        name: &'static str,
+    }
+
+    impl Steppable<WorldState> for Spawner {
+        fn start(&mut self, state: &mut WorldState) -> Result<(), StepError<String>> {
+            state.defer(spawn_command(self.name));
+            Ok(())
+        }
+    }
+
+    fn spawn_command(name: &str) -> WorldCommand {
+        WorldCommand::Spawn(Box::new(WorldObject3DInit {
+            name: name.to_owned(),
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn deferred_steppables_start_when_applied() {
+        let mut state = WorldState::new();
+        let counter = Counter::default();
+        state.defer_add_scripted_component(counter.clone());
+        assert_eq!(counter.starts.get(), 0);
+
+        state.apply_commands().unwrap();
+        assert_eq!(counter.starts.get(), 1);
+        let entries = state.scripted_components();
+        state.run_steps(&GameLoop::empty(), &entries).unwrap();
+        assert_eq!(counter.steps.get(), 1);
+    }
+
+    #[test]
+    fn commands_queued_while_applying_are_applied_too() {
+        let mut state = WorldState::new();
+        state.defer_add_scripted_component(Spawner { name: "spawned" });
+
+        state.apply_commands().unwrap();
+        assert!(state.find_by_name("spawned").is_some());
+        assert!(state.commands.is_empty());
+    }
+
+    #[test]
+    fn failed_commands_dont_stop_the_rest() {
+        let mut state = WorldState::new();
+        let gone = state.new_world_obj();
+        state.remove_world_obj(gone, ChildPolicy::Remove).unwrap();
+        state.defer(WorldCommand::Despawn(gone, ChildPolicy::Remove));
+        state.defer(spawn_command("after"));
+
+        let error = state.apply_commands().unwrap_err();
+        assert!(error.contains(&format!("No world object {:?}", gone)));
+        assert!(state.find_by_name("after").is_some());
+        assert!(state.apply_commands().is_ok());
+    }
+
+    /// Fails every step with the error `error` makes, counting its steps.
+    #[derive(Clone)]
+    struct Failing {
+        steps: Rc<Cell<u32>>,
+        error: fn() -> StepError<String>,
+    }
+
+    impl Failing {
+        fn new(error: fn() -> StepError<String>) -> Self {
+            Self {
+                steps: Default::default(),
+                error,
+            }
+        }
+    }
+
+    impl Steppable<WorldState> for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn step(&mut self, _: &mut WorldState, _: &GameLoop) -> Result<(), StepError<String>> {
+            self.steps.set(self.steps.get() + 1);
+            Err((self.error)())
+        }
+    }
+
+    fn run_frame(state: &mut WorldState) -> Result<(), String> {
+        let entries = state.scripted_components();
+        state.run_steps(&GameLoop::empty(), &entries)
+    }
+
+    #[test]
+    fn self_disabled_steppables_stop_alone() {
+        let mut state = WorldState::new();
+        let failing = Failing::new(|| StepError::SelfDisable("done".into()));
+        let counter = Counter::default();
+        state.add_scripted_component(failing.clone());
+        state.add_scripted_component(counter.clone());
+
+        run_frame(&mut state).unwrap();
+        run_frame(&mut state).unwrap();
+        assert_eq!(failing.steps.get(), 1);
+        assert_eq!(counter.steps.get(), 2);
+    }
+
+    #[test]
+    fn fatal_post_step_finishes_the_phase_first() {
+        let mut state = WorldState::new();
+        let failing = Failing::new(|| StepError::FatalPostStep("broken".into()));
+        let counter = Counter::default();
+        state.add_scripted_component(failing.clone());
+        state.add_scripted_component(counter.clone());
+
+        let error = run_frame(&mut state).unwrap_err();
+        assert!(error.contains("failing: broken"));
+        assert_eq!(counter.steps.get(), 1);
+    }
+
+    #[test]
+    fn self_disabled_subscribers_are_unsubscribed() {
+        struct Ping;
+        let mut state = WorldState::new();
+        let calls = Rc::new(Cell::new(0));
+        let calls_in = calls.clone();
+        state.subscribe(move |_: &Ping, _: &mut WorldState| {
+            calls_in.set(calls_in.get() + 1);
+            Err(StepError::SelfDisable("once is enough".into()))
+        });
+
+        for _ in 0..2 {
+            state.publish(Ping);
+            state.deliver_events().unwrap();
+        }
+        assert_eq!(calls.get(), 1);
     }
 }
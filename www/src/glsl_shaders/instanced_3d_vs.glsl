@@ -0,0 +1,21 @@
+#version 300 es
+// The line above this denotes we are using WebGL2.
+
+// Per vertex attributes, shared by every instance.
+in vec4 a_position;
+
+// Per instance attributes, advanced once per instance.
+in mat4 a_instanceMatrix;
+in vec4 a_instanceColor;
+
+// Camera transformation. The model transform comes from a_instanceMatrix.
+uniform mat4 u_transformationMatrix;
+
+out vec4 v_color;
+
+void main() {
+  gl_Position = u_transformationMatrix * a_instanceMatrix * a_position;
+
+  // Pass the instance colour directly to the fragment shader
+  v_color = a_instanceColor;
+}
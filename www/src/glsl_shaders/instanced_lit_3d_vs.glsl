@@ -0,0 +1,29 @@
+#version 300 es
+// The line above this denotes we are using WebGL2.
+
+// Per vertex attributes, shared by every instance.
+in vec4 a_position;
+in vec3 a_normal;
+
+// Per instance attributes, advanced once per instance.
+in mat4 a_instanceMatrix;
+in vec4 a_instanceColor;
+
+// Camera transformation. The model transform comes from a_instanceMatrix.
+uniform mat4 u_transformationMatrix;
+
+out vec4 v_color;
+out vec3 v_normal;
+out vec3 v_worldPos;
+out vec2 v_texcoord;
+
+void main() {
+  vec4 worldPos = a_instanceMatrix * a_position;
+  gl_Position = u_transformationMatrix * worldPos;
+
+  v_color = a_instanceColor;
+  v_normal = transpose(inverse(mat3(a_instanceMatrix))) * a_normal;
+  v_worldPos = worldPos.xyz;
+  // Instanced meshes are untextured, so sample a single texel.
+  v_texcoord = vec2(0.0);
+}
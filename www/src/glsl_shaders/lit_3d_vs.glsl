@@ -0,0 +1,28 @@
+#version 300 es
+// The line above this denotes we are using WebGL2.
+
+in vec4 a_position;
+in vec3 a_normal;
+in vec4 a_color;
+in vec2 a_texcoord;
+
+// Full model-view-projection transform.
+uniform mat4 u_transformationMatrix;
+// Model transform alone, for world space lighting.
+uniform mat4 u_model;
+// Inverse transpose of the model transform's upper 3x3.
+uniform mat3 u_normalMatrix;
+
+out vec4 v_color;
+out vec3 v_normal;
+out vec3 v_worldPos;
+out vec2 v_texcoord;
+
+void main() {
+  gl_Position = u_transformationMatrix * a_position;
+
+  v_color = a_color;
+  v_normal = u_normalMatrix * a_normal;
+  v_worldPos = (u_model * a_position).xyz;
+  v_texcoord = a_texcoord;
+}
@@ -0,0 +1,79 @@
+#version 300 es
+// Line above this indicates we're using WebGL2.
+
+precision highp float;
+
+// Must match MAX_DIRECTIONAL_LIGHTS and MAX_POINT_LIGHTS in lighting.rs.
+#define MAX_DIR_LIGHTS 4
+#define MAX_POINT_LIGHTS 4
+
+in vec4 v_color;
+in vec3 v_normal;
+in vec3 v_worldPos;
+in vec2 v_texcoord;
+
+// Multiplied with the vertex colour. White when the item has no texture.
+uniform sampler2D u_diffuseTexture;
+
+uniform vec3 u_cameraPosition;
+
+// From the item's MTL material. Ambient scales the ambient light, and
+// specular highlights use a Blinn-Phong term with the given exponent.
+uniform vec3 u_materialAmbient;
+uniform vec3 u_materialSpecular;
+uniform float u_materialShininess;
+
+uniform vec3 u_ambientLight;
+
+// Directions are the way the light travels, in world space.
+uniform int u_dirLightCount;
+uniform vec3 u_dirLightDirections[MAX_DIR_LIGHTS];
+uniform vec3 u_dirLightColors[MAX_DIR_LIGHTS];
+
+uniform int u_pointLightCount;
+uniform vec3 u_pointLightPositions[MAX_POINT_LIGHTS];
+uniform vec3 u_pointLightColors[MAX_POINT_LIGHTS];
+uniform float u_pointLightRanges[MAX_POINT_LIGHTS];
+
+out vec4 outColor;
+
+// Specular highlight from light arriving along `toLight`, in world space.
+float specular(vec3 normal, vec3 toLight, vec3 toCamera) {
+    if (dot(normal, toLight) <= 0.0) {
+        return 0.0;
+    }
+    vec3 halfway = normalize(toLight + toCamera);
+    return pow(max(dot(normal, halfway), 0.0), max(u_materialShininess, 1.0));
+}
+
+void main() {
+    vec4 albedo = v_color * texture(u_diffuseTexture, v_texcoord);
+    vec3 normal = normalize(v_normal);
+    vec3 toCamera = normalize(u_cameraPosition - v_worldPos);
+    vec3 light = u_ambientLight * u_materialAmbient;
+    vec3 highlight = vec3(0.0);
+
+    for (int i = 0; i < MAX_DIR_LIGHTS; i++) {
+        if (i >= u_dirLightCount) {
+            break;
+        }
+        vec3 toLight = -u_dirLightDirections[i];
+        light += u_dirLightColors[i] * max(dot(normal, toLight), 0.0);
+        highlight += u_dirLightColors[i] * specular(normal, toLight, toCamera);
+    }
+
+    for (int i = 0; i < MAX_POINT_LIGHTS; i++) {
+        if (i >= u_pointLightCount) {
+            break;
+        }
+        vec3 toLight = u_pointLightPositions[i] - v_worldPos;
+        float dist = length(toLight);
+        // Quadratic falloff, reaching zero at the light's range.
+        float falloff = clamp(1.0 - dist / u_pointLightRanges[i], 0.0, 1.0);
+        vec3 arriving = u_pointLightColors[i] * falloff * falloff;
+        light += arriving * max(dot(normal, toLight / dist), 0.0);
+        highlight += arriving * specular(normal, toLight / dist, toCamera);
+    }
+
+    outColor = vec4(albedo.rgb * light + u_materialSpecular * highlight, albedo.a);
+}
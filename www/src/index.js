@@ -2,18 +2,38 @@ import { webglMain } from "./shader_comp.js";
 import "./index.scss";
 
 const CANVAS = document.getElementById("drawscape");
-//const FRAG_SHADER = "src/glsl_shaders/fragment_shader.glsl";
-const FRAG_SHADER = "src/glsl_shaders/colourized_fs.glsl";
-//const VERT_SHADER = "src/glsl_shaders/vertex_shader.glsl";
-const VERT_SHADER = "src/glsl_shaders/ortho_3d_vs.glsl";
+// Program name -> shader source paths. Names must match the Rust side.
+const SHADERS = {
+    vertex_color: {
+        vert: "src/glsl_shaders/ortho_3d_vs.glsl",
+        frag: "src/glsl_shaders/colourized_fs.glsl",
+    },
+    instanced_color: {
+        vert: "src/glsl_shaders/instanced_3d_vs.glsl",
+        frag: "src/glsl_shaders/colourized_fs.glsl",
+    },
+    lit: {
+        vert: "src/glsl_shaders/lit_3d_vs.glsl",
+        frag: "src/glsl_shaders/lit_fs.glsl",
+    },
+    instanced_lit: {
+        vert: "src/glsl_shaders/instanced_lit_3d_vs.glsl",
+        frag: "src/glsl_shaders/lit_fs.glsl",
+    },
+};
+
+async function fetchText(url) {
+    return fetch(url).then(r => r.text());
+}
 
 async function main() {
-    const fragShaderSrcP = fetch(FRAG_SHADER).then(r => r.text());
-    const vertShaderSrcP = fetch(VERT_SHADER).then(r => r.text());
-    const fragShaderSrc = await fragShaderSrcP;
-    const vertShaderSrc = await vertShaderSrcP;
+    const shaderSrcs = {};
+    await Promise.all(Object.entries(SHADERS).map(async ([name, paths]) => {
+        const [vert, frag] = await Promise.all([fetchText(paths.vert), fetchText(paths.frag)]);
+        shaderSrcs[name] = { vert, frag };
+    }));
 
-    webglMain(CANVAS, fragShaderSrc, vertShaderSrc);
+    webglMain(CANVAS, shaderSrcs);
 }
 
 main();
@@ -51,7 +51,7 @@ function createProgram(gl, vertexShader, fragmentShader) {
  */
 function preloads(urls) {
   let promises = [];
-  names.forEach((n) => {
+  urls.forEach((n) => {
     promises.push(fetch(n));
   });
   return Promise.all(promises).then((responses) => {
@@ -59,7 +59,22 @@ function preloads(urls) {
   });
 }
 
-export function webglMain(canvas, fragShaderSrc, vertShaderSrc) {
+/**
+ * @param {*} gl
+ * @param {*} shaderSrcs Object mapping program names to `{ vert, frag }` sources.
+ * @returns Object mapping program names to linked programs.
+ */
+function createPrograms(gl, shaderSrcs) {
+  const programs = {};
+  for (const [name, src] of Object.entries(shaderSrcs)) {
+    const vertShader = createShader(gl, gl.VERTEX_SHADER, src.vert);
+    const fragShader = createShader(gl, gl.FRAGMENT_SHADER, src.frag);
+    programs[name] = createProgram(gl, vertShader, fragShader);
+  }
+  return programs;
+}
+
+export function webglMain(canvas, shaderSrcs) {
   const gl = canvas.getContext("webgl2", { antialias: false });
   /*
     // ================================================================
@@ -73,10 +88,9 @@ export function webglMain(canvas, fragShaderSrc, vertShaderSrc) {
   // ================================================================
   // -- Program Creation --
   // ================================================================
-  // Create the base shaders, and make the program.
-  const vertShader = createShader(gl, gl.VERTEX_SHADER, vertShaderSrc);
-  const fragShader = createShader(gl, gl.FRAGMENT_SHADER, fragShaderSrc);
-  const prgm = createProgram(gl, vertShader, fragShader);
+  // Create every program. The renderer picks which one to use per item.
+  const programs = createPrograms(gl, shaderSrcs);
+  const prgm = programs.vertex_color;
   gl.useProgram(prgm);
 
   /*
@@ -127,7 +141,12 @@ export function webglMain(canvas, fragShaderSrc, vertShaderSrc) {
 
   preloads([""]).then(preloaded => {
     let preload_wrapper = { meshes: preloaded };
-    bind_game(gl, prgm, ["u_transformationMatrix"], canvas, preload_wrapper);
+    bind_game(gl, programs, ["u_transformationMatrix"], canvas, preload_wrapper)
+      .then(game => {
+        // Lets the loop be controlled from the console, e.g. `game.stop()`.
+        window.game = game;
+        bindDebugKeys(game);
+      });
   });
 
   //const drawScene = () => {
@@ -142,3 +161,27 @@ export function webglMain(canvas, fragShaderSrc, vertShaderSrc) {
   //}
   //drawScene();
 }
+
+/**
+ * P pauses and resumes, N steps one frame while paused, and [ and ] halve
+ * and double the time scale.
+ * @param {GameHandle} game
+ */
+function bindDebugKeys(game) {
+  document.addEventListener("keydown", event => {
+    switch (event.key) {
+      case "p":
+        game.is_paused() ? game.resume() : game.pause();
+        break;
+      case "n":
+        game.step_once();
+        break;
+      case "[":
+        game.set_time_scale(game.get_time_scale() / 2);
+        break;
+      case "]":
+        game.set_time_scale(game.get_time_scale() * 2);
+        break;
+    }
+  });
+}